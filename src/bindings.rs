@@ -0,0 +1,250 @@
+// which keys and pad buttons do what. loaded from bindings.toml in the user's
+// config dir, and changeable from the options menu.
+
+use std::{fmt::Display, fs, path::PathBuf};
+
+use directories::ProjectDirs;
+use ggez::{
+	input::gamepad::gilrs::{Axis, Button, Gamepad},
+	winit::event::VirtualKeyCode,
+};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::movement::{Diagonal, Socd};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum Action {
+	Up,
+	Down,
+	Left,
+	Right,
+	#[strum(serialize = "rotate CCW")]
+	RotateCCW,
+	#[strum(serialize = "rotate CW")]
+	RotateCW,
+	#[strum(serialize = "rotate CCW (2)")]
+	RotateCCW2,
+}
+
+// every action can have as many keys as you like.
+// anything left out of the file keeps its default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+	pub up: Vec<VirtualKeyCode>,
+	pub down: Vec<VirtualKeyCode>,
+	pub left: Vec<VirtualKeyCode>,
+	pub right: Vec<VirtualKeyCode>,
+	pub ccw: Vec<VirtualKeyCode>,
+	pub cw: Vec<VirtualKeyCode>,
+	pub ccw2: Vec<VirtualKeyCode>,
+	pub socd: Socd,
+	pub diagonal: Diagonal,
+	// has to stay last, toml wants tables after everything else.
+	pub pad: PadBindings,
+}
+
+// gamepads and arcade sticks. the sticks listed here (x axis, y axis) count as
+// directions once they're pushed further than the deadzone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PadBindings {
+	pub up: Vec<Button>,
+	pub down: Vec<Button>,
+	pub left: Vec<Button>,
+	pub right: Vec<Button>,
+	pub ccw: Vec<Button>,
+	pub cw: Vec<Button>,
+	pub ccw2: Vec<Button>,
+	pub sticks: Vec<[Axis; 2]>,
+	pub deadzone: f32,
+}
+
+impl Default for PadBindings {
+	fn default() -> Self {
+		PadBindings {
+			up: vec![Button::DPadUp],
+			down: vec![Button::DPadDown],
+			left: vec![Button::DPadLeft],
+			right: vec![Button::DPadRight],
+			// A, B, C on a stick
+			ccw: vec![Button::South],
+			cw: vec![Button::East],
+			ccw2: vec![Button::West],
+			// lots of arcade sticks show up as a hat, which is the dpad axes.
+			sticks: vec![
+				[Axis::LeftStickX, Axis::LeftStickY],
+				[Axis::DPadX, Axis::DPadY],
+			],
+			deadzone: 0.5,
+		}
+	}
+}
+
+impl PadBindings {
+	pub fn buttons(&self, action: Action) -> &Vec<Button> {
+		match action {
+			Action::Up => &self.up,
+			Action::Down => &self.down,
+			Action::Left => &self.left,
+			Action::Right => &self.right,
+			Action::RotateCCW => &self.ccw,
+			Action::RotateCW => &self.cw,
+			Action::RotateCCW2 => &self.ccw2,
+		}
+	}
+
+	pub fn buttons_mut(&mut self, action: Action) -> &mut Vec<Button> {
+		match action {
+			Action::Up => &mut self.up,
+			Action::Down => &mut self.down,
+			Action::Left => &mut self.left,
+			Action::Right => &mut self.right,
+			Action::RotateCCW => &mut self.ccw,
+			Action::RotateCW => &mut self.cw,
+			Action::RotateCCW2 => &mut self.ccw2,
+		}
+	}
+
+	// buttons come in as events, but sticks have to be looked at.
+	pub fn is_stick_held(&self, action: Action, pad: &Gamepad) -> bool {
+		// up on a stick is positive
+		let pushed = |axis: Axis, sign: f32| pad.value(axis) * sign > self.deadzone;
+
+		self.sticks.iter().any(|[x, y]| match action {
+			Action::Up => pushed(*y, 1.),
+			Action::Down => pushed(*y, -1.),
+			Action::Left => pushed(*x, -1.),
+			Action::Right => pushed(*x, 1.),
+			_ => false,
+		})
+	}
+}
+
+impl Default for Bindings {
+	fn default() -> Self {
+		Bindings {
+			up: vec![VirtualKeyCode::W],
+			down: vec![VirtualKeyCode::S],
+			left: vec![VirtualKeyCode::A],
+			right: vec![VirtualKeyCode::D],
+			ccw: vec![VirtualKeyCode::H],
+			cw: vec![VirtualKeyCode::J],
+			ccw2: vec![VirtualKeyCode::K],
+			socd: Socd::default(),
+			diagonal: Diagonal::default(),
+			pad: PadBindings::default(),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+	NoConfigDir,
+	Io(PathBuf, std::io::Error),
+	Parse(PathBuf, toml::de::Error),
+	Unbound(Action),
+	BadDeadzone(f32),
+}
+
+impl Display for BindingsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BindingsError::NoConfigDir => write!(f, "couldn't find a config directory"),
+			BindingsError::Io(path, err) => {
+				write!(f, "couldn't access {}: {}", path.display(), err)
+			}
+			BindingsError::Parse(path, err) => {
+				write!(f, "{} is invalid: {}", path.display(), err)
+			}
+			BindingsError::Unbound(action) => write!(f, "nothing is bound to {}", action),
+			BindingsError::BadDeadzone(deadzone) => {
+				write!(
+					f,
+					"the deadzone should be between 0 and 1, not {}",
+					deadzone
+				)
+			}
+		}
+	}
+}
+
+impl Bindings {
+	pub fn keys(&self, action: Action) -> &Vec<VirtualKeyCode> {
+		match action {
+			Action::Up => &self.up,
+			Action::Down => &self.down,
+			Action::Left => &self.left,
+			Action::Right => &self.right,
+			Action::RotateCCW => &self.ccw,
+			Action::RotateCW => &self.cw,
+			Action::RotateCCW2 => &self.ccw2,
+		}
+	}
+
+	pub fn keys_mut(&mut self, action: Action) -> &mut Vec<VirtualKeyCode> {
+		match action {
+			Action::Up => &mut self.up,
+			Action::Down => &mut self.down,
+			Action::Left => &mut self.left,
+			Action::Right => &mut self.right,
+			Action::RotateCCW => &mut self.ccw,
+			Action::RotateCW => &mut self.cw,
+			Action::RotateCCW2 => &mut self.ccw2,
+		}
+	}
+
+	fn validate(&self) -> Result<(), BindingsError> {
+		use strum::IntoEnumIterator;
+
+		// an action only needs a key, pads are optional.
+		if let Some(action) = Action::iter().find(|a| self.keys(*a).is_empty()) {
+			return Err(BindingsError::Unbound(action));
+		}
+
+		if !(0.0..1.0).contains(&self.pad.deadzone) {
+			return Err(BindingsError::BadDeadzone(self.pad.deadzone));
+		}
+
+		Ok(())
+	}
+}
+
+pub fn config_path() -> Result<PathBuf, BindingsError> {
+	ProjectDirs::from("", "zkldi", "rgm")
+		.map(|dirs| dirs.config_dir().join("bindings.toml"))
+		.ok_or(BindingsError::NoConfigDir)
+}
+
+// no file is fine, that just means the defaults.
+pub fn load() -> Result<Bindings, BindingsError> {
+	let path = config_path()?;
+
+	if !path.exists() {
+		return Ok(Bindings::default());
+	}
+
+	let data = fs::read_to_string(&path).map_err(|err| BindingsError::Io(path.clone(), err))?;
+
+	let bindings: Bindings =
+		toml::from_str(&data).map_err(|err| BindingsError::Parse(path.clone(), err))?;
+
+	bindings.validate()?;
+
+	Ok(bindings)
+}
+
+pub fn save(bindings: &Bindings) -> Result<PathBuf, BindingsError> {
+	let path = config_path()?;
+
+	let data = toml::to_string(bindings).expect("bindings are always valid toml");
+
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir).map_err(|err| BindingsError::Io(dir.to_path_buf(), err))?;
+	}
+
+	fs::write(&path, data).map_err(|err| BindingsError::Io(path.clone(), err))?;
+
+	Ok(path)
+}
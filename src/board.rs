@@ -1,31 +1,75 @@
-use std::{array::IntoIter, fmt::Display};
+use std::{array::IntoIter, fmt::Display, ops::RangeInclusive};
 
 use ggez::graphics::{self, Color, DrawParam};
+use serde::{Deserialize, Serialize};
 
-use crate::piece::Piece;
+use crate::{piece::Piece, FPS};
 
-pub const BOARD_SIZE: (usize, usize) = (10, 21);
 pub const SQUARE_SIZE: f32 = 20.0;
 pub const BOARD_OFFSET_X: f32 = SQUARE_SIZE * 6.0;
 pub const BOARD_OFFSET_Y: f32 = SQUARE_SIZE * 3.0;
 const BOARD_BORDER: f32 = 1.0;
-
-#[derive(Debug, Clone, Copy)]
+// what blocks look like once they've gone grey
+const MONO_COLOR: Color = Color::new(1.0, 1.0, 1.0, 0.7);
+// the arcade's highlight around the edge of the stack
+const OUTLINE_COLOR: Color = Color::new(1.0, 1.0, 1.0, 0.8);
+const OUTLINE_SIZE: f32 = 2.0;
+// fading blocks take this long to go, at the end of their time
+const FADE_OUT_FRAMES: i32 = FPS as i32;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Square {
 	Empty,
 	Filled(Color),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardSize {
+	pub width: usize,
+	// rows inside the frame
+	pub visible: usize,
+	// rows above the frame, for pieces to spawn into
+	pub hidden: usize,
+}
+
+// what mode select allows. 4 wide is as narrow as an I piece lies flat,
+// 8 rows fit a big I standing up, and 24 is as tall as the window goes.
+// there has to be at least a row above the frame to spawn into.
+pub const WIDTH_RANGE: RangeInclusive<usize> = 4..=10;
+pub const VISIBLE_RANGE: RangeInclusive<usize> = 8..=24;
+pub const HIDDEN_RANGE: RangeInclusive<usize> = 1..=20;
+
+impl BoardSize {
+	// replays, savestates and the command line could say anything.
+	pub fn is_playable(&self) -> bool {
+		WIDTH_RANGE.contains(&self.width)
+			&& VISIBLE_RANGE.contains(&self.visible)
+			&& HIDDEN_RANGE.contains(&self.hidden)
+	}
+}
+
+// TGM's: 10x20, and a row on top.
+pub const DEFAULT_BOARD_SIZE: BoardSize = BoardSize {
+	width: 10,
+	visible: 20,
+	hidden: 1,
+};
+
+// rows go from the bottom up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Board {
-	pub state: [[Square; BOARD_SIZE.0]; BOARD_SIZE.1],
+	pub size: BoardSize,
+	pub state: Vec<Vec<Square>>,
+	// frames until each square disappears, for the credit roll.
+	// None stays, and Some(0) is already gone.
+	vanish: Vec<Vec<Option<i32>>>,
+	// what newly locked squares get
+	pub vanish_after: Option<i32>,
 }
 
 impl Default for Board {
 	fn default() -> Self {
-		let state = [[Square::Empty; BOARD_SIZE.0]; BOARD_SIZE.1];
-
-		Board { state }
+		Board::new(DEFAULT_BOARD_SIZE)
 	}
 }
 
@@ -48,6 +92,60 @@ impl Display for Board {
 }
 
 impl Board {
+	pub fn new(size: BoardSize) -> Self {
+		Board {
+			size,
+			state: vec![Board::empty_row(size.width); size.visible + size.hidden],
+			vanish: vec![vec![None; size.width]; size.visible + size.hidden],
+			vanish_after: None,
+		}
+	}
+
+	fn empty_row(width: usize) -> Vec<Square> {
+		vec![Square::Empty; width]
+	}
+
+	// a playable size, with every row and timer the size says there should be.
+	pub fn is_well_formed(&self) -> bool {
+		let height = self.size.visible + self.size.hidden;
+		let width = self.size.width;
+
+		self.size.is_playable()
+			&& self.state.len() == height
+			&& self.vanish.len() == height
+			&& self.state.iter().all(|row| row.len() == width)
+			&& self.vanish.iter().all(|row| row.len() == width)
+	}
+
+	pub fn width(&self) -> usize {
+		self.size.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.state.len()
+	}
+
+	// whether (x, y) is on the board at all
+	pub fn contains(&self, x: i32, y: i32) -> bool {
+		(0..self.width() as i32).contains(&x) && (0..self.height() as i32).contains(&y)
+	}
+
+	// a new piece goes in the middle, with its top row just above the frame.
+	pub fn spawn(&self, piece: Piece) -> Piece {
+		let scale = if piece.big { 2 } else { 1 };
+
+		Piece {
+			x: ((self.width() / scale) as i32 - 3) / 2,
+			y: (self.size.visible / scale) as i32 + 1,
+			..piece
+		}
+	}
+
+	// where row y's squares are drawn, going up from the bottom of the frame.
+	pub fn row_y(&self, y: i32) -> f32 {
+		BOARD_OFFSET_Y + SQUARE_SIZE * (self.size.visible as i32 - 1 - y) as f32
+	}
+
 	pub fn draw(&self, canvas: &mut graphics::Canvas) {
 		let c = Color {
 			a: 0.5,
@@ -56,9 +154,9 @@ impl Board {
 			b: 1.0,
 		};
 
-		let width = SQUARE_SIZE * BOARD_SIZE.0 as f32 + BOARD_BORDER;
+		let width = SQUARE_SIZE * self.width() as f32 + BOARD_BORDER;
 
-		let height = SQUARE_SIZE * (BOARD_SIZE.1 - 1) as f32 + BOARD_BORDER;
+		let height = SQUARE_SIZE * self.size.visible as f32 + BOARD_BORDER;
 
 		canvas.draw(
 			&graphics::Quad,
@@ -99,82 +197,145 @@ impl Board {
 		);
 	}
 
+	// returns how many lines were cleared. big pieces clear lines in pairs,
+	// and each pair counts as one.
 	pub fn lock_piece(&mut self, piece: Piece) -> i32 {
-		let bx = piece.get_box().b;
-
-		let mut y: i32 = piece.y - 1;
+		let color = piece.p_type.get_color();
 
-		for row in bx {
-			let mut x: i32 = piece.x;
-
-			for sqr in row {
-				if matches!(sqr, Square::Filled(_)) {
-					self.state[y as usize][x as usize] = sqr;
-				}
+		for (x, y) in piece.cells() {
+			self.state[y as usize][x as usize] = Square::Filled(color);
+			self.vanish[y as usize][x as usize] = self.vanish_after;
+		}
 
-				x += 1;
-			}
+		let lines = self.clear_lines();
 
-			y -= 1;
+		if piece.big {
+			(lines + 1) / 2
+		} else {
+			lines
 		}
-
-		self.clear_lines()
 	}
 
 	fn clear_lines(&mut self) -> i32 {
-		let mut lines_cleared = 0;
+		let height = self.height();
+
+		// the timers go wherever their row goes
+		let rows: Vec<_> = self
+			.state
+			.drain(..)
+			.zip(self.vanish.drain(..))
+			.filter(|(row, _)| !row.iter().all(|s| matches!(s, Square::Filled(_))))
+			.collect();
 
-		let array_iter = self.state.into_iter();
+		let lines_cleared = height - rows.len();
 
-		let new_rows: Vec<[Square; BOARD_SIZE.0]> = array_iter
-			.filter(|row| {
-				if row.iter().all(|s| matches!(s, Square::Filled(_))) {
-					lines_cleared += 1;
-					false
-				} else {
-					true
-				}
-			})
-			.collect();
+		(self.state, self.vanish) = rows.into_iter().unzip();
+		self.state.resize(height, Board::empty_row(self.width()));
+		self.vanish.resize(height, vec![None; self.width()]);
+
+		lines_cleared as i32
+	}
+
+	pub fn monoify(&mut self, mono_frames: i32) {
+		let rows = (mono_frames / 10) as usize;
 
-		for y in 0..BOARD_SIZE.1 {
-			if let Some(r) = new_rows.get(y) {
-				self.state[y] = *r;
-			} else {
-				self.state[y] = [Square::Empty; BOARD_SIZE.0];
+		for row in self.state.iter_mut().take(rows) {
+			for s in row.iter_mut() {
+				*s = s.mono();
 			}
 		}
-
-		lines_cleared
 	}
 
-	pub fn monoify(&mut self, mono_frames: i32) {
-		let array_iter = self.state.into_iter();
+	// pushes every row up one, with row going in at the bottom.
+	// false if that pushed anything off the top.
+	pub fn push_up(&mut self, row: Vec<Square>) -> bool {
+		let top = self.state.pop();
+		let fits = !top.is_some_and(|top| top.iter().any(|s| matches!(s, Square::Filled(_))));
 
-		let mut y = 0;
+		self.state.insert(0, row);
+		self.vanish.pop();
+		self.vanish.insert(0, vec![None; self.width()]);
 
-		let new_rows: Vec<[Square; BOARD_SIZE.0]> = array_iter
-			.map_while(|row| {
-				y += 1;
+		fits
+	}
 
-				if y > mono_frames / 10 {
-					return None;
-				}
+	// one frame closer to vanishing, for every square that's going to.
+	pub fn tick_vanish(&mut self) {
+		for timer in self.vanish.iter_mut().flatten().flatten() {
+			*timer = (*timer - 1).max(0);
+		}
+	}
 
-				Some(row.map(|s| match s {
-					Square::Empty => Square::Empty,
-					Square::Filled(_) => Square::Filled(Color::new(1.0, 1.0, 1.0, 0.7)),
-				}))
-			})
-			.collect();
+	// everything shows again, like at the end of the roll.
+	pub fn reveal(&mut self) {
+		self.vanish = vec![vec![None; self.width()]; self.height()];
+		self.vanish_after = None;
+	}
 
-		for y in 0..BOARD_SIZE.1 {
-			if let Some(r) = new_rows.get(y) {
-				self.state[y] = *r;
+	// the square as it should look right now. vanished squares are empty,
+	// and fading ones see through.
+	pub fn shown(&self, x: usize, y: usize) -> Square {
+		match (self.state[y][x], self.vanish[y][x]) {
+			(Square::Filled(_), Some(0)) => Square::Empty,
+			(Square::Filled(color), Some(frames)) if frames < FADE_OUT_FRAMES => {
+				Square::Filled(Color {
+					a: color.a * frames as f32 / FADE_OUT_FRAMES as f32,
+					..color
+				})
 			}
+			(square, _) => square,
 		}
 	}
 
+	// a line along every edge between a square that shows and one that doesn't,
+	// for the rows inside the frame.
+	pub fn draw_outline(&self, canvas: &mut graphics::Canvas) {
+		let showing = |x: i32, y: i32| {
+			self.contains(x, y) && matches!(self.shown(x as usize, y as usize), Square::Filled(_))
+		};
+
+		for y in 0..self.size.visible as i32 {
+			for x in 0..self.width() as i32 {
+				let Square::Filled(color) = self.shown(x as usize, y as usize) else {
+					continue;
+				};
+
+				let outline = Color {
+					a: OUTLINE_COLOR.a * color.a,
+					..OUTLINE_COLOR
+				};
+				let left = BOARD_OFFSET_X + SQUARE_SIZE * x as f32;
+				let top = self.row_y(y);
+				let far = SQUARE_SIZE - OUTLINE_SIZE;
+
+				// the floor and walls aren't edges
+				let edges = [
+					(x - 1, y, [left, top], [OUTLINE_SIZE, SQUARE_SIZE]),
+					(x + 1, y, [left + far, top], [OUTLINE_SIZE, SQUARE_SIZE]),
+					(x, y + 1, [left, top], [SQUARE_SIZE, OUTLINE_SIZE]),
+					(x, y - 1, [left, top + far], [SQUARE_SIZE, OUTLINE_SIZE]),
+				];
+
+				for (nx, ny, dest, scale) in edges {
+					if self.contains(nx, ny) && !showing(nx, ny) {
+						canvas.draw(
+							&graphics::Quad,
+							DrawParam::default().color(outline).scale(scale).dest(dest),
+						);
+					}
+				}
+			}
+		}
+	}
+
+	pub fn filled_count(&self) -> usize {
+		self.state
+			.iter()
+			.flatten()
+			.filter(|s| matches!(s, Square::Filled(_)))
+			.count()
+	}
+
 	pub fn is_empty(&self) -> bool {
 		self.state
 			.iter()
@@ -189,6 +350,12 @@ pub struct PieceBox {
 }
 
 impl PieceBox {
+	pub fn mono(self) -> Self {
+		PieceBox {
+			b: self.b.map(|row| row.map(Square::mono)),
+		}
+	}
+
 	pub fn draw(self, canvas: &mut graphics::Canvas, x: f32, y: f32) {
 		let orig_x = x;
 
@@ -209,6 +376,13 @@ impl PieceBox {
 }
 
 impl Square {
+	pub fn mono(self) -> Self {
+		match self {
+			Square::Empty => Square::Empty,
+			Square::Filled(_) => Square::Filled(MONO_COLOR),
+		}
+	}
+
 	pub fn draw(self, canvas: &mut graphics::Canvas, x: f32, y: f32) {
 		match self {
 			Square::Empty => (),
@@ -0,0 +1,787 @@
+// everything around the game itself: title, menus, pausing and results.
+
+use std::ops::RangeInclusive;
+
+use ggez::{
+	event::EventHandler,
+	glam::Vec2,
+	graphics::{self, Color, Text},
+	input::{
+		gamepad::{gilrs::Button, GamepadId},
+		keyboard::KeyInput,
+	},
+	winit::event::VirtualKeyCode,
+	Context, GameResult,
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+	bindings::{self, Action, Bindings},
+	board::{BoardSize, BOARD_OFFSET_X, BOARD_OFFSET_Y, HIDDEN_RANGE, VISIBLE_RANGE, WIDTH_RANGE},
+	grading::GradingKind,
+	input::{Input, InputState},
+	movement::{Direction, Movement, Rotation},
+	randomiser::RandomiserKind,
+	results::draw_results,
+	rules::{GameSettings, RollKind, RuleSet, TorikanPreset},
+	scoring::ScoringKind,
+	GameState, FPS,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+	pub show_next: bool,
+	pub show_lock_delay: bool,
+	pub show_inputs: bool,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			show_next: true,
+			show_lock_delay: true,
+			show_inputs: false,
+		}
+	}
+}
+
+#[derive(Debug)]
+enum Screen {
+	Title {
+		cursor: usize,
+	},
+	ModeSelect {
+		cursor: usize,
+	},
+	Options {
+		cursor: usize,
+	},
+	Bindings {
+		cursor: usize,
+		listening: Option<Listen>,
+	},
+	Playing(Box<GameState>),
+	Paused {
+		game: Box<GameState>,
+		cursor: usize,
+	},
+	Results {
+		game: Box<GameState>,
+		cursor: usize,
+	},
+}
+
+// waiting on the rebinding screen for the next key press.
+#[derive(Debug, Clone, Copy)]
+enum Listen {
+	Replace(Action),
+	Add(Action),
+}
+
+// menus are driven by the same keys as the game.
+// a rotate button (or enter) picks, the other one (or escape) goes back.
+#[derive(Debug, Default)]
+struct MenuInput {
+	up: bool,
+	down: bool,
+	left: bool,
+	right: bool,
+	confirm: bool,
+	back: bool,
+}
+
+impl MenuInput {
+	fn new(
+		previous: &Movement,
+		movement: &Movement,
+		ctx: &Context,
+		pad_pressed: Option<Button>,
+	) -> Self {
+		let horizontal =
+			if previous.horizontal != movement.horizontal || movement.horizontal_pressed {
+				movement.horizontal
+			} else {
+				None
+			};
+
+		let vertical = if previous.vertical != movement.vertical || movement.vertical_pressed {
+			movement.vertical
+		} else {
+			None
+		};
+
+		let rot = movement.new_rotation(previous);
+
+		MenuInput {
+			up: vertical == Some(Direction::Up),
+			down: vertical == Some(Direction::Down),
+			left: horizontal == Some(Direction::Left),
+			right: horizontal == Some(Direction::Right),
+			confirm: matches!(rot, Some(Rotation::CCW) | Some(Rotation::CCW2))
+				|| ctx.keyboard.is_key_just_pressed(VirtualKeyCode::Return),
+			back: rot == Some(Rotation::CW)
+				|| ctx.keyboard.is_key_just_pressed(VirtualKeyCode::Escape)
+				|| pad_pressed == Some(Button::Start),
+		}
+	}
+
+	fn move_cursor(&self, cursor: usize, len: usize) -> usize {
+		if self.up {
+			(cursor + len - 1) % len
+		} else if self.down {
+			(cursor + 1) % len
+		} else {
+			cursor
+		}
+	}
+}
+
+// the next or previous value of an enum, wrapping round.
+fn cycle<T: IntoEnumIterator + PartialEq + Copy>(value: T, forward: bool) -> T {
+	let values: Vec<T> = T::iter().collect();
+	let idx = values.iter().position(|v| *v == value).unwrap_or(0);
+
+	if forward {
+		values[(idx + 1) % values.len()]
+	} else {
+		values[(idx + values.len() - 1) % values.len()]
+	}
+}
+
+const TITLE_ITEMS: [&str; 3] = ["start", "options", "quit"];
+const PAUSE_ITEMS: [&str; 3] = ["resume", "retry", "quit to title"];
+const RESULTS_ITEMS: [&str; 4] = ["retry", "retry same seed", "save replay", "back to title"];
+const MODE_SELECT_LEN: usize = 14;
+const OPTIONS_LEN: usize = 4;
+// every action, then opposites and diagonals, then "defaults" and "save"
+const BINDINGS_RULES_LEN: usize = 2;
+const BINDINGS_EXTRA_ITEMS: [&str; 2] = ["reset to defaults", "save"];
+
+fn step_within(value: usize, change: i32, range: &RangeInclusive<usize>) -> usize {
+	(value as i32 + change).clamp(*range.start() as i32, *range.end() as i32) as usize
+}
+
+fn on_off(b: bool) -> &'static str {
+	if b {
+		"on"
+	} else {
+		"off"
+	}
+}
+
+fn draw_menu(
+	canvas: &mut graphics::Canvas,
+	title: &str,
+	items: &[String],
+	cursor: usize,
+	pos: Vec2,
+) {
+	let mut text = format!("{}\n\n", title);
+
+	for (i, item) in items.iter().enumerate() {
+		let marker = if i == cursor { ">" } else { " " };
+
+		text.push_str(&format!("{} {}\n", marker, item));
+	}
+
+	canvas.draw(&Text::new(text), pos);
+}
+
+pub struct App {
+	screen: Screen,
+	settings: GameSettings,
+	options: Options,
+	bindings: Bindings,
+	input: InputState,
+	previous_movement: Movement,
+	// pads don't have "just pressed", so we remember the last button down
+	// for one update.
+	pad_pressed: Option<Button>,
+	// shown under the menus, for errors and such
+	message: Option<String>,
+}
+
+impl App {
+	pub fn new() -> Self {
+		let (bindings, message) = match bindings::load() {
+			Ok(b) => (b, None),
+			Err(err) => {
+				eprintln!("{}", err);
+				(
+					Bindings::default(),
+					Some(format!("{}\nusing the default controls.", err)),
+				)
+			}
+		};
+
+		App {
+			screen: Screen::Title { cursor: 0 },
+			settings: GameSettings::default(),
+			options: Options::default(),
+			bindings,
+			input: InputState::default(),
+			previous_movement: Movement::default(),
+			pad_pressed: None,
+			message,
+		}
+	}
+
+	// mode select starts on this board, for when the command line sized it.
+	pub fn with_board(self, board: BoardSize) -> Self {
+		App {
+			settings: GameSettings {
+				board,
+				..self.settings
+			},
+			..self
+		}
+	}
+
+	// skips the menus, for when the command line already said what to play.
+	pub fn playing(game: GameState) -> Self {
+		App {
+			screen: Screen::Playing(Box::new(game)),
+			..App::new()
+		}
+	}
+
+	fn mode_select_items(&self) -> Vec<String> {
+		vec![
+			format!("start level   < {:03} >", self.settings.start_level),
+			format!("rules         < {} >", self.settings.rules),
+			format!("randomiser    < {} >", self.settings.randomiser),
+			format!("grading       < {} >", self.settings.grading),
+			format!("scoring       < {} >", self.settings.scoring),
+			format!("DAS           < {:2} >", self.settings.shift.das),
+			format!("ARR           < {:2} >", self.settings.shift.arr),
+			format!(
+				"torikan       < {} >",
+				TorikanPreset::find(&self.settings.torikans)
+					.map_or("custom".to_string(), |p| p.to_string())
+			),
+			format!("big           < {} >", on_off(self.settings.big)),
+			format!("width         < {:2} >", self.settings.board.width),
+			format!("height        < {:2} >", self.settings.board.visible),
+			format!("hidden rows   < {:2} >", self.settings.board.hidden),
+			format!("roll          < {} >", self.settings.roll),
+			"start".to_string(),
+		]
+	}
+
+	fn options_items(&self) -> Vec<String> {
+		vec![
+			format!("next piece    < {} >", on_off(self.options.show_next)),
+			format!("lock delay    < {} >", on_off(self.options.show_lock_delay)),
+			format!("input display < {} >", on_off(self.options.show_inputs)),
+			"controls".to_string(),
+		]
+	}
+
+	fn bindings_items(&self, listening: Option<Listen>) -> Vec<String> {
+		let mut items: Vec<String> = Action::iter()
+			.map(|action| {
+				let inputs: Vec<String> = match listening {
+					Some(Listen::Replace(a)) if a == action => vec!["press a key...".to_string()],
+					_ => self
+						.bindings
+						.keys(action)
+						.iter()
+						.map(|k| format!("{:?}", k))
+						.chain(
+							self.bindings
+								.pad
+								.buttons(action)
+								.iter()
+								.map(|b| format!("pad {:?}", b)),
+						)
+						.collect(),
+				};
+
+				let adding = match listening {
+					Some(Listen::Add(a)) if a == action => " + press a key...",
+					_ => "",
+				};
+
+				format!("{:<16}{}{}", action.to_string(), inputs.join(", "), adding)
+			})
+			.collect();
+
+		items.push(format!("{:<16}< {} >", "opposites", self.bindings.socd));
+		items.push(format!("{:<16}< {} >", "diagonals", self.bindings.diagonal));
+		items.extend(BINDINGS_EXTRA_ITEMS.iter().map(|s| s.to_string()));
+
+		items
+	}
+
+	fn update_bindings(
+		&mut self,
+		ctx: &mut Context,
+		cursor: usize,
+		listening: Option<Listen>,
+		input: &MenuInput,
+	) -> Screen {
+		if let Some(listen) = listening {
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::Escape) {
+				return Screen::Bindings {
+					cursor,
+					listening: None,
+				};
+			}
+
+			// a pad button goes into the pad bindings and leaves the keys alone.
+			if let Some(button) = self.pad_pressed {
+				match listen {
+					Listen::Replace(action) => {
+						*self.bindings.pad.buttons_mut(action) = vec![button]
+					}
+					Listen::Add(action) => {
+						let buttons = self.bindings.pad.buttons_mut(action);
+
+						if !buttons.contains(&button) {
+							buttons.push(button);
+						}
+					}
+				}
+
+				return Screen::Bindings {
+					cursor,
+					listening: None,
+				};
+			}
+
+			let pressed = ctx
+				.keyboard
+				.pressed_keys()
+				.iter()
+				.copied()
+				.find(|k| ctx.keyboard.is_key_just_pressed(*k));
+
+			return match (pressed, listen) {
+				(Some(key), Listen::Replace(action)) => {
+					*self.bindings.keys_mut(action) = vec![key];
+					Screen::Bindings {
+						cursor,
+						listening: None,
+					}
+				}
+				(Some(key), Listen::Add(action)) => {
+					let keys = self.bindings.keys_mut(action);
+
+					if !keys.contains(&key) {
+						keys.push(key);
+					}
+
+					Screen::Bindings {
+						cursor,
+						listening: None,
+					}
+				}
+				(None, _) => Screen::Bindings {
+					cursor,
+					listening: Some(listen),
+				},
+			};
+		}
+
+		let actions: Vec<Action> = Action::iter().collect();
+		let cursor = input.move_cursor(
+			cursor,
+			actions.len() + BINDINGS_RULES_LEN + BINDINGS_EXTRA_ITEMS.len(),
+		);
+
+		if input.back {
+			return Screen::Options { cursor: 3 };
+		}
+
+		let mut listening = None;
+		let changing = input.left || input.right || input.confirm;
+
+		match cursor.checked_sub(actions.len()) {
+			None => {
+				let action = actions[cursor];
+
+				if input.confirm {
+					listening = Some(Listen::Replace(action));
+				} else if input.right {
+					listening = Some(Listen::Add(action));
+				} else if input.left {
+					// an action always keeps at least one key
+					let keys = self.bindings.keys_mut(action);
+
+					if keys.len() > 1 {
+						keys.pop();
+					}
+				}
+			}
+			Some(0) if changing => self.bindings.socd = cycle(self.bindings.socd, !input.left),
+			Some(1) if changing => {
+				self.bindings.diagonal = cycle(self.bindings.diagonal, !input.left);
+			}
+			Some(2) if input.confirm => self.bindings = Bindings::default(),
+			Some(3) if input.confirm => {
+				self.message = Some(match bindings::save(&self.bindings) {
+					Ok(path) => format!("saved controls to {}", path.display()),
+					Err(err) => format!("{}", err),
+				});
+			}
+			_ => (),
+		}
+
+		Screen::Bindings { cursor, listening }
+	}
+
+	fn update_screen(&mut self, ctx: &mut Context, screen: Screen, input: &MenuInput) -> Screen {
+		match screen {
+			Screen::Title { cursor } => {
+				let cursor = input.move_cursor(cursor, TITLE_ITEMS.len());
+
+				if input.confirm {
+					match cursor {
+						0 => Screen::ModeSelect { cursor: 0 },
+						1 => Screen::Options { cursor: 0 },
+						_ => {
+							ctx.request_quit();
+							Screen::Title { cursor }
+						}
+					}
+				} else {
+					Screen::Title { cursor }
+				}
+			}
+
+			Screen::ModeSelect { cursor } => {
+				let cursor = input.move_cursor(cursor, MODE_SELECT_LEN);
+
+				if input.left || input.right {
+					match cursor {
+						0 => {
+							let change = if input.right { 100 } else { -100 };
+
+							self.settings.start_level =
+								(self.settings.start_level + change).clamp(0, 900);
+						}
+						1 => {
+							self.settings.rules =
+								cycle::<RuleSet>(self.settings.rules, input.right);
+
+							match self.settings.rules {
+								RuleSet::Death => {
+									self.settings = self.settings.clone().with_death()
+								}
+								RuleSet::Shirase => {
+									self.settings = self.settings.clone().with_shirase()
+								}
+								_ => (),
+							}
+						}
+						2 => {
+							self.settings.randomiser =
+								cycle::<RandomiserKind>(self.settings.randomiser, input.right)
+						}
+						3 => {
+							self.settings.grading =
+								cycle::<GradingKind>(self.settings.grading, input.right)
+						}
+						4 => {
+							self.settings.scoring =
+								cycle::<ScoringKind>(self.settings.scoring, input.right)
+						}
+						5 => {
+							let change = if input.right { 1 } else { -1 };
+
+							self.settings.shift.das =
+								(self.settings.shift.das + change).clamp(0, 30);
+						}
+						6 => {
+							let change = if input.right { 1 } else { -1 };
+
+							self.settings.shift.arr =
+								(self.settings.shift.arr + change).clamp(0, 10);
+						}
+						7 => {
+							let preset = TorikanPreset::find(&self.settings.torikans)
+								.unwrap_or(TorikanPreset::Off);
+
+							self.settings.torikans = cycle(preset, input.right).torikans();
+						}
+						8 => self.settings.big = !self.settings.big,
+						9 => {
+							// big pieces only fit even widths
+							let step = if self.settings.can_be_big() { 2 } else { 1 };
+							let change = if input.right { step } else { -step };
+
+							self.settings.board.width =
+								step_within(self.settings.board.width, change, &WIDTH_RANGE);
+						}
+						10 => {
+							let change = if input.right { 1 } else { -1 };
+
+							self.settings.board.visible =
+								step_within(self.settings.board.visible, change, &VISIBLE_RANGE);
+						}
+						11 => {
+							// big pieces need an even count
+							let step = if self.settings.can_be_big() { 2 } else { 1 };
+							let change = if input.right { step } else { -step };
+
+							self.settings.board.hidden =
+								step_within(self.settings.board.hidden, change, &HIDDEN_RANGE);
+						}
+						12 => {
+							self.settings.roll = cycle::<RollKind>(self.settings.roll, input.right)
+						}
+						_ => (),
+					}
+
+					// turning big on can leave the board too small for it
+					self.settings.board = self.settings.board_size();
+				}
+
+				if input.back {
+					Screen::Title { cursor: 0 }
+				} else if input.confirm {
+					Screen::Playing(Box::new(GameState::new(
+						self.settings.clone(),
+						rand::random(),
+					)))
+				} else {
+					Screen::ModeSelect { cursor }
+				}
+			}
+
+			Screen::Options { cursor } => {
+				let cursor = input.move_cursor(cursor, OPTIONS_LEN);
+
+				if cursor == 3 {
+					if input.confirm {
+						return Screen::Bindings {
+							cursor: 0,
+							listening: None,
+						};
+					}
+				} else if input.left || input.right || input.confirm {
+					match cursor {
+						0 => self.options.show_next = !self.options.show_next,
+						1 => self.options.show_lock_delay = !self.options.show_lock_delay,
+						_ => self.options.show_inputs = !self.options.show_inputs,
+					}
+				}
+
+				if input.back {
+					Screen::Title { cursor: 1 }
+				} else {
+					Screen::Options { cursor }
+				}
+			}
+
+			Screen::Bindings { cursor, listening } => {
+				self.update_bindings(ctx, cursor, listening, input)
+			}
+
+			Screen::Playing(mut game) => {
+				if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::Escape)
+					|| self.pad_pressed == Some(Button::Start)
+				{
+					return Screen::Paused { game, cursor: 0 };
+				}
+
+				game.handle_hotkeys(ctx);
+
+				while ctx.time.check_update_time(FPS) {
+					game.tick(self.input.take_frame(ctx, &self.bindings));
+				}
+
+				if game.is_showing_results() {
+					Screen::Results { game, cursor: 0 }
+				} else {
+					Screen::Playing(game)
+				}
+			}
+
+			Screen::Paused { mut game, cursor } => {
+				let cursor = input.move_cursor(cursor, PAUSE_ITEMS.len());
+
+				if input.back {
+					Screen::Playing(game)
+				} else if input.confirm {
+					match cursor {
+						0 => Screen::Playing(game),
+						1 => {
+							game.retry();
+							Screen::Playing(game)
+						}
+						_ => Screen::Title { cursor: 0 },
+					}
+				} else {
+					Screen::Paused { game, cursor }
+				}
+			}
+
+			Screen::Results { mut game, cursor } => {
+				let cursor = input.move_cursor(cursor, RESULTS_ITEMS.len());
+
+				if input.back {
+					Screen::Title { cursor: 0 }
+				} else if input.confirm {
+					match cursor {
+						0 => {
+							game.restart(rand::random());
+							Screen::Playing(game)
+						}
+						1 => {
+							game.restart(game.seed());
+							Screen::Playing(game)
+						}
+						2 => {
+							game.save_replay();
+							Screen::Results { game, cursor }
+						}
+						_ => Screen::Title { cursor: 0 },
+					}
+				} else {
+					Screen::Results { game, cursor }
+				}
+			}
+		}
+	}
+}
+
+impl EventHandler for App {
+	fn update(&mut self, ctx: &mut Context) -> GameResult {
+		let movement = self.input.movement(ctx, &self.bindings);
+		let input = MenuInput::new(&self.previous_movement, &movement, ctx, self.pad_pressed);
+		self.previous_movement = movement;
+
+		let screen = std::mem::replace(&mut self.screen, Screen::Title { cursor: 0 });
+		let was_playing = matches!(screen, Screen::Playing(_));
+
+		self.screen = self.update_screen(ctx, screen, &input);
+		self.pad_pressed = None;
+
+		// the game only runs while it's being played, everything else
+		// just throws the time away so we don't catch up on it later.
+		// the game uses up presses as it runs frames, the menus do it here.
+		if !matches!(self.screen, Screen::Playing(_)) {
+			while ctx.time.check_update_time(FPS) {}
+
+			self.input.end_frame();
+		} else if !was_playing {
+			// the press that started or resumed the game isn't a rotation
+			self.input.end_frame();
+		}
+
+		Ok(())
+	}
+
+	// not the default, which quits on escape.
+	fn key_down_event(
+		&mut self,
+		_ctx: &mut Context,
+		input: KeyInput,
+		repeated: bool,
+	) -> GameResult {
+		if let (Some(key), false) = (input.keycode, repeated) {
+			self.input.press(Input::Key(key));
+		}
+
+		Ok(())
+	}
+
+	fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> GameResult {
+		if let Some(key) = input.keycode {
+			self.input.release(Input::Key(key));
+		}
+
+		Ok(())
+	}
+
+	fn gamepad_button_down_event(
+		&mut self,
+		_ctx: &mut Context,
+		btn: Button,
+		_id: GamepadId,
+	) -> GameResult {
+		self.input.press(Input::Button(btn));
+		self.pad_pressed = Some(btn);
+
+		Ok(())
+	}
+
+	fn gamepad_button_up_event(
+		&mut self,
+		_ctx: &mut Context,
+		btn: Button,
+		_id: GamepadId,
+	) -> GameResult {
+		self.input.release(Input::Button(btn));
+
+		Ok(())
+	}
+
+	fn draw(&mut self, ctx: &mut Context) -> GameResult {
+		let mut canvas = graphics::Canvas::from_frame(ctx, Color::BLACK);
+
+		let menu_pos = Vec2::new(BOARD_OFFSET_X, BOARD_OFFSET_Y);
+
+		match &self.screen {
+			Screen::Title { cursor } => {
+				let items: Vec<String> = TITLE_ITEMS.iter().map(|s| s.to_string()).collect();
+
+				draw_menu(&mut canvas, "RGM", &items, *cursor, menu_pos);
+			}
+			Screen::ModeSelect { cursor } => {
+				draw_menu(
+					&mut canvas,
+					"MODE SELECT",
+					&self.mode_select_items(),
+					*cursor,
+					menu_pos,
+				);
+			}
+			Screen::Options { cursor } => {
+				draw_menu(
+					&mut canvas,
+					"OPTIONS",
+					&self.options_items(),
+					*cursor,
+					menu_pos,
+				);
+			}
+			Screen::Bindings { cursor, listening } => {
+				draw_menu(
+					&mut canvas,
+					"CONTROLS\nH/enter: set  D: add a key  A: remove a key",
+					&self.bindings_items(*listening),
+					*cursor,
+					menu_pos,
+				);
+			}
+			Screen::Playing(game) => game.draw(&mut canvas, &self.options),
+			Screen::Paused { game, cursor } => {
+				// like the arcade, you don't get to look at the board while paused.
+				game.draw_board_frame(&mut canvas);
+
+				let items: Vec<String> = PAUSE_ITEMS.iter().map(|s| s.to_string()).collect();
+
+				draw_menu(&mut canvas, "PAUSED", &items, *cursor, menu_pos);
+			}
+			Screen::Results { game, cursor } => {
+				game.draw(&mut canvas, &self.options);
+
+				draw_results(&mut canvas, game);
+
+				let items: Vec<String> = RESULTS_ITEMS.iter().map(|s| s.to_string()).collect();
+
+				draw_menu(&mut canvas, "", &items, *cursor, Vec2::new(500., 60.));
+			}
+		}
+
+		if let Some(message) = &self.message {
+			if !matches!(self.screen, Screen::Playing(_)) {
+				canvas.draw(&Text::new(message.as_str()), Vec2::new(20., 540.));
+			}
+		}
+
+		canvas.finish(ctx)
+	}
+}
@@ -0,0 +1,825 @@
+// fumen v115 encoding/decoding.
+// this is the format everyone uses to share setups, see https://fumen.zui.jp/
+// and knewjade's tetris-fumen for the reference implementation.
+
+use std::fmt::Display;
+
+use ggez::graphics::Color;
+
+use crate::{
+	board::{Board, Square},
+	movement::RotIndex,
+	piece::{Piece, PieceType},
+};
+
+const PREFIX: &str = "v115@";
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8; 95] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_CHAR_VALUES: u32 = COMMENT_TABLE.len() as u32 + 1;
+
+// fumen always has a 10x23 field with a garbage row hanging underneath it.
+const FIELD_WIDTH: usize = 10;
+const FIELD_TOP: usize = 23;
+const FIELD_BLOCKS: usize = FIELD_WIDTH * (FIELD_TOP + 1);
+
+// fumen stores pieces as 1..=7, 8 is garbage.
+const GRAY: u8 = 8;
+
+// fumen positions pieces around a center point, SRS style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FumenRotation {
+	Reverse,
+	Right,
+	Spawn,
+	Left,
+}
+
+const ROTATIONS: [FumenRotation; 4] = [
+	FumenRotation::Spawn,
+	FumenRotation::Right,
+	FumenRotation::Reverse,
+	FumenRotation::Left,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FumenError {
+	UnsupportedVersion,
+	InvalidCharacter(char),
+	UnexpectedEnd,
+	InvalidPiece(u32),
+	InvalidCommentCharacter(u32),
+	// the field has blocks above what our board can hold
+	FieldTooTall,
+}
+
+impl Display for FumenError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			FumenError::UnsupportedVersion => {
+				write!(f, "only {} fumen codes are supported", PREFIX)
+			}
+			FumenError::InvalidCharacter(c) => write!(f, "invalid character '{}' in fumen", c),
+			FumenError::UnexpectedEnd => write!(f, "fumen ended unexpectedly"),
+			FumenError::InvalidPiece(p) => write!(f, "invalid piece value {} in fumen", p),
+			FumenError::InvalidCommentCharacter(v) => {
+				write!(f, "invalid comment character value {} in fumen", v)
+			}
+			FumenError::FieldTooTall => write!(f, "fumen field is taller than the board"),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct FumenPage {
+	pub board: Board,
+	// the piece shown on this page, if any
+	pub piece: Option<Piece>,
+	// pieces after the current one, read from a "#Q=" quiz comment.
+	// if the page has no piece, the quiz's current piece comes first.
+	pub queue: Vec<PieceType>,
+}
+
+fn piece_value(p_type: PieceType) -> u8 {
+	match p_type {
+		PieceType::I => 1,
+		PieceType::L => 2,
+		PieceType::O => 3,
+		PieceType::Z => 4,
+		PieceType::T => 5,
+		PieceType::J => 6,
+		PieceType::S => 7,
+	}
+}
+
+fn value_piece(value: u8) -> Option<PieceType> {
+	match value {
+		1 => Some(PieceType::I),
+		2 => Some(PieceType::L),
+		3 => Some(PieceType::O),
+		4 => Some(PieceType::Z),
+		5 => Some(PieceType::T),
+		6 => Some(PieceType::J),
+		7 => Some(PieceType::S),
+		_ => None,
+	}
+}
+
+// cells relative to the piece center, in spawn orientation. y goes up.
+fn fumen_cells(p_type: PieceType, rotation: FumenRotation) -> [(i32, i32); 4] {
+	let cells = match p_type {
+		PieceType::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+		PieceType::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+		PieceType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+		PieceType::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+		PieceType::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+		PieceType::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+		PieceType::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+	};
+
+	cells.map(|(x, y)| match rotation {
+		FumenRotation::Spawn => (x, y),
+		FumenRotation::Right => (y, -x),
+		FumenRotation::Reverse => (-x, -y),
+		FumenRotation::Left => (-y, x),
+	})
+}
+
+// cells of one of our pieces, in board coordinates.
+fn piece_cells(piece: Piece) -> Vec<(i32, i32)> {
+	let mut cells = vec![];
+
+	for (r, row) in piece.get_box().b.iter().enumerate() {
+		for (c, sqr) in row.iter().enumerate() {
+			if matches!(sqr, Square::Filled(_)) {
+				cells.push((piece.x + c as i32, piece.y - 1 - r as i32));
+			}
+		}
+	}
+
+	cells
+}
+
+// shifts a set of cells so the lowest x and y are both 0, and sorts it.
+// returns the shift that was removed.
+fn normalise(cells: &mut [(i32, i32)]) -> (i32, i32) {
+	let min_x = cells.iter().map(|c| c.0).min().unwrap_or(0);
+	let min_y = cells.iter().map(|c| c.1).min().unwrap_or(0);
+
+	for c in cells.iter_mut() {
+		c.0 -= min_x;
+		c.1 -= min_y;
+	}
+
+	cells.sort();
+
+	(min_x, min_y)
+}
+
+// fumen stores some pieces one off from their real center, for compatibility
+// with old versions of the editor. this turns a stored location into the center.
+fn stored_to_center(p_type: PieceType, rotation: FumenRotation, x: i32, y: i32) -> (i32, i32) {
+	match (p_type, rotation) {
+		(PieceType::O, FumenRotation::Left) => (x + 1, y - 1),
+		(PieceType::O, FumenRotation::Reverse) => (x + 1, y),
+		(PieceType::O, FumenRotation::Spawn) => (x, y - 1),
+		(PieceType::I, FumenRotation::Reverse) => (x + 1, y),
+		(PieceType::I, FumenRotation::Left) => (x, y - 1),
+		(PieceType::S, FumenRotation::Spawn) => (x, y - 1),
+		(PieceType::S, FumenRotation::Right) => (x - 1, y),
+		(PieceType::Z, FumenRotation::Spawn) => (x, y - 1),
+		(PieceType::Z, FumenRotation::Left) => (x + 1, y),
+		_ => (x, y),
+	}
+}
+
+fn center_to_stored(p_type: PieceType, rotation: FumenRotation, x: i32, y: i32) -> (i32, i32) {
+	let (cx, cy) = stored_to_center(p_type, rotation, x, y);
+
+	(x - (cx - x), y - (cy - y))
+}
+
+// finds the piece in our rotation system that covers the same cells.
+fn to_piece(p_type: PieceType, rotation: FumenRotation, x: i32, y: i32) -> Option<Piece> {
+	let mut target = fumen_cells(p_type, rotation).map(|(cx, cy)| (cx + x, cy + y));
+	let (tx, ty) = normalise(&mut target);
+
+	for rot_idx in [RotIndex::Neutral, RotIndex::CW, RotIndex::U, RotIndex::CCW] {
+		let piece = Piece {
+			p_type,
+			rot_idx,
+			x: 0,
+			y: 0,
+			big: false,
+		};
+
+		let mut cells = piece_cells(piece);
+		let (px, py) = normalise(&mut cells);
+
+		if cells == target {
+			return Some(Piece {
+				x: tx - px,
+				y: ty - py,
+				..piece
+			});
+		}
+	}
+
+	None
+}
+
+// the inverse of to_piece. returns the rotation and center of the piece.
+fn from_piece(piece: Piece) -> (FumenRotation, i32, i32) {
+	let mut target = piece_cells(piece);
+	let (tx, ty) = normalise(&mut target);
+
+	for rotation in ROTATIONS {
+		let mut cells = fumen_cells(piece.p_type, rotation).to_vec();
+		let (fx, fy) = normalise(&mut cells);
+
+		if cells == target {
+			return (rotation, tx - fx, ty - fy);
+		}
+	}
+
+	unreachable!("every piece has a fumen orientation")
+}
+
+// fumen only knows about piece colors, so we have to guess from ours.
+fn square_value(sqr: Square) -> u8 {
+	match sqr {
+		Square::Empty => 0,
+		Square::Filled(color) => [
+			PieceType::I,
+			PieceType::L,
+			PieceType::O,
+			PieceType::Z,
+			PieceType::T,
+			PieceType::J,
+			PieceType::S,
+		]
+		.into_iter()
+		.find(|p| p.get_color() == color)
+		.map(piece_value)
+		.unwrap_or(GRAY),
+	}
+}
+
+fn value_square(value: u8) -> Square {
+	match value_piece(value) {
+		Some(p) => Square::Filled(p.get_color()),
+		None if value == 0 => Square::Empty,
+		None => Square::Filled(Color::new(0.5, 0.5, 0.5, 1.0)),
+	}
+}
+
+// fumen fields go top to bottom, so index 0 is the top left.
+fn field_index(x: usize, y: usize) -> usize {
+	(FIELD_TOP - y - 1) * FIELD_WIDTH + x
+}
+
+fn field_to_board(field: &[u8; FIELD_BLOCKS]) -> Result<Board, FumenError> {
+	let mut board = Board::default();
+
+	for y in 0..FIELD_TOP {
+		for x in 0..FIELD_WIDTH {
+			let value = field[field_index(x, y)];
+
+			if y >= board.height() {
+				if value != 0 {
+					return Err(FumenError::FieldTooTall);
+				}
+
+				continue;
+			}
+
+			board.state[y][x] = value_square(value);
+		}
+	}
+
+	Ok(board)
+}
+
+fn board_to_field(board: &Board) -> [u8; FIELD_BLOCKS] {
+	let mut field = [0; FIELD_BLOCKS];
+
+	for (y, row) in board.state.iter().enumerate().take(FIELD_TOP) {
+		for (x, sqr) in row.iter().enumerate() {
+			field[field_index(x, y)] = square_value(*sqr);
+		}
+	}
+
+	field
+}
+
+// JS escape(), which fumen runs comments through.
+fn escape(s: &str) -> String {
+	let mut out = String::new();
+
+	for c in s.chars() {
+		if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+			out.push(c);
+		} else if (c as u32) < 256 {
+			out.push_str(&format!("%{:02X}", c as u32));
+		} else {
+			for unit in c.encode_utf16(&mut [0; 2]) {
+				out.push_str(&format!("%u{:04X}", unit));
+			}
+		}
+	}
+
+	out
+}
+
+fn unescape(s: &str) -> String {
+	let mut units: Vec<u16> = vec![];
+	let chars: Vec<char> = s.chars().collect();
+	let mut i = 0;
+
+	while i < chars.len() {
+		let hex = |from: usize, len: usize| {
+			chars
+				.get(from..from + len)
+				.and_then(|h| u16::from_str_radix(&h.iter().collect::<String>(), 16).ok())
+		};
+
+		if chars[i] == '%' {
+			if chars.get(i + 1) == Some(&'u') {
+				if let Some(unit) = hex(i + 2, 4) {
+					units.push(unit);
+					i += 6;
+					continue;
+				}
+			} else if let Some(unit) = hex(i + 1, 2) {
+				units.push(unit);
+				i += 3;
+				continue;
+			}
+		}
+
+		units.push(chars[i] as u16);
+		i += 1;
+	}
+
+	String::from_utf16_lossy(&units)
+}
+
+// "#Q=[hold](current)next" is how fumen quizzes write the queue.
+// a piece on the page is the current piece, so it isn't queued twice.
+fn parse_queue(comment: &str, has_piece: bool) -> Vec<PieceType> {
+	let Some(quiz) = comment.strip_prefix("#Q=") else {
+		return vec![];
+	};
+
+	// hold isn't a thing in this game
+	let quiz = match quiz.find(']') {
+		Some(idx) => &quiz[idx + 1..],
+		None => quiz,
+	};
+
+	let quiz = match quiz.find(')') {
+		Some(idx) if has_piece => &quiz[idx + 1..],
+		_ => quiz,
+	};
+
+	quiz.chars().filter_map(PieceType::from_letter).collect()
+}
+
+struct Reader<'a> {
+	values: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Reader<'_> {
+	fn poll(&mut self, count: u32) -> Result<u32, FumenError> {
+		let mut value = 0;
+
+		for i in 0..count {
+			// fumen inserts '?' every so often for line breaks
+			let c = loop {
+				match self.values.next() {
+					Some('?') => continue,
+					Some(c) => break c,
+					None => return Err(FumenError::UnexpectedEnd),
+				}
+			};
+
+			let v = ENCODE_TABLE
+				.iter()
+				.position(|e| *e as char == c)
+				.ok_or(FumenError::InvalidCharacter(c))? as u32;
+
+			value += v * 64u32.pow(i);
+		}
+
+		Ok(value)
+	}
+
+	fn is_done(&mut self) -> bool {
+		while self.values.peek() == Some(&'?') {
+			self.values.next();
+		}
+
+		self.values.peek().is_none()
+	}
+}
+
+fn push_value(out: &mut String, value: u32, count: u32) {
+	let mut value = value;
+
+	for _ in 0..count {
+		out.push(ENCODE_TABLE[(value % 64) as usize] as char);
+		value /= 64;
+	}
+}
+
+// places a piece on a raw fumen field.
+fn place_on_field(
+	field: &mut [u8; FIELD_BLOCKS],
+	p_type: PieceType,
+	rotation: FumenRotation,
+	x: i32,
+	y: i32,
+) {
+	for (cx, cy) in fumen_cells(p_type, rotation) {
+		let (px, py) = (x + cx, y + cy);
+
+		if (0..FIELD_WIDTH as i32).contains(&px) && (0..FIELD_TOP as i32).contains(&py) {
+			field[field_index(px as usize, py as usize)] = piece_value(p_type);
+		}
+	}
+}
+
+// clears full lines, like fumen does between pages, piece or not.
+fn clear_lines_on_field(field: &mut [u8; FIELD_BLOCKS]) {
+	let mut rows: Vec<[u8; FIELD_WIDTH]> = (0..FIELD_TOP)
+		.map(|y| {
+			let mut row = [0; FIELD_WIDTH];
+
+			for (x, v) in row.iter_mut().enumerate() {
+				*v = field[field_index(x, y)];
+			}
+
+			row
+		})
+		.filter(|row| row.contains(&0))
+		.collect();
+
+	rows.resize(FIELD_TOP, [0; FIELD_WIDTH]);
+
+	for (y, row) in rows.iter().enumerate() {
+		for (x, v) in row.iter().enumerate() {
+			field[field_index(x, y)] = *v;
+		}
+	}
+}
+
+// pushes the garbage row up into the field.
+fn rise_on_field(field: &mut [u8; FIELD_BLOCKS]) {
+	field.copy_within(FIELD_WIDTH.., 0);
+
+	for v in field[FIELD_BLOCKS - FIELD_WIDTH..].iter_mut() {
+		*v = 0;
+	}
+}
+
+fn mirror_on_field(field: &mut [u8; FIELD_BLOCKS]) {
+	for row in field[..FIELD_TOP * FIELD_WIDTH].chunks_mut(FIELD_WIDTH) {
+		row.reverse();
+	}
+}
+
+pub fn decode(code: &str) -> Result<Vec<FumenPage>, FumenError> {
+	// people often paste the whole url
+	let code = match code.find(PREFIX) {
+		Some(idx) => &code[idx + PREFIX.len()..],
+		None => return Err(FumenError::UnsupportedVersion),
+	};
+
+	let mut reader = Reader {
+		values: code.trim().chars().peekable(),
+	};
+
+	let mut pages = vec![];
+	let mut field = [0u8; FIELD_BLOCKS];
+	let mut repeat = 0;
+	let mut comment = String::new();
+
+	while !reader.is_done() {
+		// the field is stored as a run-length encoded diff from the last page
+		if repeat > 0 {
+			repeat -= 1;
+		} else {
+			let mut index = 0;
+			let mut changed = true;
+
+			while index < FIELD_BLOCKS {
+				let block = reader.poll(2)? as usize;
+				let diff = block / FIELD_BLOCKS;
+				let count = block % FIELD_BLOCKS + 1;
+
+				if diff == 8 && count == FIELD_BLOCKS {
+					changed = false;
+				}
+
+				for v in field.iter_mut().skip(index).take(count) {
+					*v = (*v as i32 + diff as i32 - 8) as u8;
+				}
+
+				index += count;
+			}
+
+			if !changed {
+				repeat = reader.poll(1)?;
+			}
+		}
+
+		let mut action = reader.poll(3)?;
+
+		let piece_type = action % 8;
+		action /= 8;
+		let rotation = [
+			FumenRotation::Reverse,
+			FumenRotation::Right,
+			FumenRotation::Spawn,
+			FumenRotation::Left,
+		][(action % 4) as usize];
+		action /= 4;
+		let location = (action % FIELD_BLOCKS as u32) as i32;
+		action /= FIELD_BLOCKS as u32;
+		let rise = action % 2 == 1;
+		action /= 2;
+		let mirror = action % 2 == 1;
+		action /= 2;
+		// colorize
+		action /= 2;
+		let has_comment = action % 2 == 1;
+		action /= 2;
+		let lock = action % 2 == 0;
+
+		if has_comment {
+			let length = reader.poll(2)? as usize;
+			let mut raw = String::new();
+
+			for _ in (0..length).step_by(4) {
+				let mut value = reader.poll(5)?;
+
+				for _ in 0..4 {
+					let c = value % COMMENT_CHAR_VALUES;
+					let c = COMMENT_TABLE
+						.get(c as usize)
+						.ok_or(FumenError::InvalidCommentCharacter(c))?;
+
+					raw.push(*c as char);
+					value /= COMMENT_CHAR_VALUES;
+				}
+			}
+
+			raw.truncate(length);
+			comment = unescape(&raw);
+		}
+
+		let operation = match piece_type {
+			0 => None,
+			p => {
+				let p_type = value_piece(p as u8).ok_or(FumenError::InvalidPiece(p))?;
+
+				let x = location % FIELD_WIDTH as i32;
+				let y = FIELD_TOP as i32 - location / FIELD_WIDTH as i32 - 1;
+				let (x, y) = stored_to_center(p_type, rotation, x, y);
+
+				Some((p_type, x, y))
+			}
+		};
+
+		let piece = operation.and_then(|(p_type, x, y)| to_piece(p_type, rotation, x, y));
+
+		pages.push(FumenPage {
+			board: field_to_board(&field)?,
+			piece,
+			queue: parse_queue(&comment, piece.is_some()),
+		});
+
+		// work out what the next page starts from
+		if lock {
+			if let Some((p_type, x, y)) = operation {
+				place_on_field(&mut field, p_type, rotation, x, y);
+			}
+
+			clear_lines_on_field(&mut field);
+
+			if rise {
+				rise_on_field(&mut field);
+			}
+
+			if mirror {
+				mirror_on_field(&mut field);
+			}
+		}
+	}
+
+	Ok(pages)
+}
+
+// encodes a single page. the queue, if any, goes in a quiz comment.
+pub fn encode(board: &Board, piece: Option<Piece>, queue: &[PieceType]) -> String {
+	let mut out = String::new();
+	let field = board_to_field(board);
+
+	// diff against an empty field
+	let mut index = 0;
+	let mut is_empty = true;
+
+	while index < FIELD_BLOCKS {
+		let diff = field[index] as usize + 8;
+		let mut count = 1;
+
+		while index + count < FIELD_BLOCKS && field[index + count] == field[index] {
+			count += 1;
+		}
+
+		if diff != 8 {
+			is_empty = false;
+		}
+
+		push_value(&mut out, (diff * FIELD_BLOCKS + count - 1) as u32, 2);
+		index += count;
+	}
+
+	if is_empty {
+		// no pages repeat this field
+		push_value(&mut out, 0, 1);
+	}
+
+	let comment = match (piece, queue.is_empty()) {
+		(_, true) => String::new(),
+		(Some(p), false) => format!(
+			"#Q=[]({:?}){}",
+			p.p_type,
+			queue.iter().map(|p| format!("{:?}", p)).collect::<String>()
+		),
+		(None, false) => format!(
+			"#Q=[]({:?}){}",
+			queue[0],
+			queue[1..]
+				.iter()
+				.map(|p| format!("{:?}", p))
+				.collect::<String>()
+		),
+	};
+
+	let (piece_type, rotation, location) = match piece {
+		Some(p) => {
+			let (rotation, x, y) = from_piece(p);
+			let (x, y) = center_to_stored(p.p_type, rotation, x, y);
+
+			let location = (FIELD_TOP as i32 - y - 1) * FIELD_WIDTH as i32 + x;
+
+			if (0..FIELD_BLOCKS as i32).contains(&location) {
+				(piece_value(p.p_type) as u32, rotation, location as u32)
+			} else {
+				// can't be represented, leave it out
+				(0, FumenRotation::Reverse, 0)
+			}
+		}
+		None => (0, FumenRotation::Reverse, 0),
+	};
+
+	let rotation = match rotation {
+		FumenRotation::Reverse => 0,
+		FumenRotation::Right => 1,
+		FumenRotation::Spawn => 2,
+		FumenRotation::Left => 3,
+	};
+
+	// lock, comment, colorize, mirror, rise
+	let mut action = 0;
+	action = action * 2 + (!comment.is_empty()) as u32;
+	action = action * 2 + 1;
+	action *= 2;
+	action *= 2;
+	action = action * FIELD_BLOCKS as u32 + location;
+	action = action * 4 + rotation;
+	action = action * 8 + piece_type;
+
+	push_value(&mut out, action, 3);
+
+	if !comment.is_empty() {
+		let escaped = escape(&comment);
+
+		push_value(&mut out, escaped.len() as u32, 2);
+
+		for chunk in escaped.as_bytes().chunks(4) {
+			let mut value = 0;
+
+			for c in chunk.iter().rev() {
+				let v = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0) as u32;
+
+				value = value * COMMENT_CHAR_VALUES + v;
+			}
+
+			push_value(&mut out, value, 5);
+		}
+	}
+
+	// the fumen site breaks long codes up with '?', 42 characters in
+	// and then every 47 after that
+	let mut wrapped = String::from(PREFIX);
+
+	for (i, c) in out.chars().enumerate() {
+		if out.len() >= 41 && i >= 42 && (i - 42) % 47 == 0 {
+			wrapped.push('?');
+		}
+
+		wrapped.push(c);
+	}
+
+	wrapped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{board::Square, practice::PracticeConfig};
+
+	fn test_board() -> Board {
+		let mut board = Board::default();
+
+		for x in 0..9 {
+			board.state[0][x] = Square::Filled(PieceType::I.get_color());
+		}
+
+		board.state[1][0] = Square::Filled(PieceType::L.get_color());
+		board
+	}
+
+	#[test]
+	fn round_trip_with_piece() {
+		let board = test_board();
+		let piece = Piece {
+			y: 5,
+			..board.spawn(PieceType::T.to_piece())
+		};
+
+		let code = encode(&board, Some(piece), &[PieceType::S, PieceType::Z]);
+		let pages = decode(&code).unwrap();
+
+		assert_eq!(pages.len(), 1);
+		assert_eq!(pages[0].board.to_string(), board.to_string());
+		assert_eq!(pages[0].piece.map(|p| p.cells()), Some(piece.cells()));
+		assert_eq!(pages[0].queue, vec![PieceType::S, PieceType::Z]);
+
+		// the page's piece is dealt once, then the queue
+		let config = PracticeConfig::from_fumen(pages[0].clone());
+		assert_eq!(config.queue, vec![PieceType::T, PieceType::S, PieceType::Z]);
+	}
+
+	#[test]
+	fn round_trip_without_piece() {
+		let board = test_board();
+
+		let code = encode(&board, None, &[PieceType::I, PieceType::O, PieceType::J]);
+		let pages = decode(&code).unwrap();
+
+		assert_eq!(pages[0].board.to_string(), board.to_string());
+		assert!(pages[0].piece.is_none());
+		assert_eq!(
+			pages[0].queue,
+			vec![PieceType::I, PieceType::O, PieceType::J]
+		);
+	}
+
+	// an action that locks, with no piece, colorized like fumen's own
+	fn pieceless_action(comment: bool) -> u32 {
+		((comment as u32 * 2 + 1) * 2 * 2) * FIELD_BLOCKS as u32 * 4 * 8
+	}
+
+	#[test]
+	fn bad_comment_characters_are_errors() {
+		let mut code = String::from(PREFIX);
+
+		// an empty field, then a one character comment that's off the end of the table
+		push_value(&mut code, (8 * FIELD_BLOCKS + FIELD_BLOCKS - 1) as u32, 2);
+		push_value(&mut code, 0, 1);
+		push_value(&mut code, pieceless_action(true), 3);
+		push_value(&mut code, 1, 2);
+		push_value(&mut code, COMMENT_CHAR_VALUES - 1, 5);
+
+		assert_eq!(
+			decode(&code).unwrap_err(),
+			FumenError::InvalidCommentCharacter(COMMENT_CHAR_VALUES - 1)
+		);
+	}
+
+	#[test]
+	fn lines_clear_without_a_piece() {
+		let mut board = Board::default();
+
+		for x in 0..10 {
+			board.state[0][x] = Square::Filled(PieceType::I.get_color());
+		}
+
+		// a second page with the same field, which the first page's lock has cleared
+		let mut code = encode(&board, None, &[]);
+		push_value(&mut code, (8 * FIELD_BLOCKS + FIELD_BLOCKS - 1) as u32, 2);
+		push_value(&mut code, 0, 1);
+		push_value(&mut code, pieceless_action(false), 3);
+
+		let pages = decode(&code).unwrap();
+
+		assert_eq!(pages.len(), 2);
+		assert!(!pages[0].board.is_empty());
+		assert!(pages[1].board.is_empty());
+	}
+
+	#[test]
+	fn quiz_queue() {
+		assert_eq!(
+			parse_queue("#Q=[L](T)SZ", false),
+			vec![PieceType::T, PieceType::S, PieceType::Z]
+		);
+		assert_eq!(
+			parse_queue("#Q=[L](T)SZ", true),
+			vec![PieceType::S, PieceType::Z]
+		);
+		assert!(parse_queue("just a comment", false).is_empty());
+	}
+}
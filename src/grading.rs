@@ -0,0 +1,670 @@
+// grading. every mode picks a grader, which hears about locks and frames
+// and decides what grade to show.
+
+use std::{fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+use crate::{
+	board::{Board, Square},
+	rules::{RollKind, DEATH_TORIKAN},
+	scoring::{GMRequirements, Grade, PlayerRecord, SECTION_COUNT},
+	FPS,
+};
+
+// what a grader gets told when a piece locks.
+#[derive(Debug, Clone, Copy)]
+pub struct GradeEvent {
+	// 0 if the piece didn't clear anything
+	pub lines: i32,
+	pub level_before: i32,
+	// consecutive line clearing pieces, counting this one
+	pub combo: i32,
+	pub score: i32,
+	pub gm_requirements: GMRequirements,
+}
+
+pub trait GradingSystem {
+	fn on_lock(&mut self, event: &GradeEvent);
+
+	// one frame of the game. active is false during ARE and line clears.
+	fn on_frame(&mut self, _active: bool, _combo: i32) {}
+
+	// the level went from old_level to new_level on this frame.
+	fn on_level(&mut self, _old_level: i32, _new_level: i32, _frames: u32) {}
+
+	// the game reached its max level: 999, or 1300 for Shirase.
+	fn on_game_clear(&mut self, _record: &PlayerRecord, _frames: u32) {}
+
+	// the credit roll this grader gives once the game's cleared, if it picks one.
+	fn roll(&self) -> Option<RollKind> {
+		None
+	}
+
+	// survived the credit roll.
+	fn on_roll_clear(&mut self, _roll: RollKind) {}
+
+	// how far ahead of the level the game's speed is.
+	fn speed_levels(&self, _level: i32) -> i32 {
+		0
+	}
+
+	// COOLs and REGRETs, for graders that have them.
+	fn judgements(&self) -> Option<&[SectionJudgement; SECTION_COUNT]> {
+		None
+	}
+
+	fn grade(&self) -> Grade;
+}
+
+// TGM3's verdict on a section. a section can be both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionJudgement {
+	// reached x70 quickly
+	pub cool: bool,
+	// took too long over the whole section
+	pub regret: bool,
+}
+
+impl Display for SectionJudgement {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match (self.cool, self.regret) {
+			(true, true) => write!(f, "COOL!! REGRET!!"),
+			(true, false) => write!(f, "COOL!!"),
+			(false, true) => write!(f, "REGRET!!"),
+			(false, false) => Ok(()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum GradingKind {
+	// score thresholds
+	#[strum(serialize = "TGM1")]
+	Tgm1,
+	// hidden grade points that decay over time
+	#[strum(serialize = "TAP Master")]
+	TapMaster,
+	// TAP's points, plus COOLs and REGRETs for each section
+	#[strum(serialize = "TGM3 Master")]
+	Tgm3Master,
+	// M for 500 in time, GM for 999
+	Death,
+	// a grade every 100 levels
+	Shirase,
+}
+
+// the grader for each kind, kept as an enum so it can go in snapshots and savestates.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Grader {
+	Tgm1(Tgm1Grading),
+	TapMaster(TapGrading),
+	Tgm3Master(Tgm3Grading),
+	Death(DeathGrading),
+	Shirase(ShiraseGrading),
+}
+
+impl Grader {
+	pub fn new(kind: GradingKind) -> Self {
+		match kind {
+			GradingKind::Tgm1 => Grader::Tgm1(Tgm1Grading::default()),
+			GradingKind::TapMaster => Grader::TapMaster(TapGrading::default()),
+			GradingKind::Tgm3Master => Grader::Tgm3Master(Tgm3Grading::default()),
+			GradingKind::Death => Grader::Death(DeathGrading::default()),
+			GradingKind::Shirase => Grader::Shirase(ShiraseGrading::default()),
+		}
+	}
+
+	pub fn system(&self) -> &dyn GradingSystem {
+		match self {
+			Grader::Tgm1(g) => g,
+			Grader::TapMaster(g) => g,
+			Grader::Tgm3Master(g) => g,
+			Grader::Death(g) => g,
+			Grader::Shirase(g) => g,
+		}
+	}
+
+	pub fn system_mut(&mut self) -> &mut dyn GradingSystem {
+		match self {
+			Grader::Tgm1(g) => g,
+			Grader::TapMaster(g) => g,
+			Grader::Tgm3Master(g) => g,
+			Grader::Death(g) => g,
+			Grader::Shirase(g) => g,
+		}
+	}
+}
+
+// TGM1: your grade is your score.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tgm1Grading {
+	grade: Grade,
+}
+
+impl Default for Tgm1Grading {
+	fn default() -> Self {
+		Tgm1Grading { grade: Grade::N9 }
+	}
+}
+
+fn is_gm(gm_requirements: &GMRequirements) -> bool {
+	let one_second = Duration::new(1, 0);
+	let one_minute = one_second * 60;
+	let four_min_15_sec = one_minute * 4 + one_second * 15;
+	let seven_min_30_sec = one_minute * 7 + one_second * 30;
+	let thirteen_min_30_sec = one_minute * 13 + one_second * 30;
+
+	if let (Some(three_hundred), Some(five_hundred), Some(game_end)) = (
+		gm_requirements.three_hundred,
+		gm_requirements.five_hundred,
+		gm_requirements.game_end,
+	) {
+		if three_hundred.score < 12_000 || three_hundred.time <= four_min_15_sec {
+			return false;
+		}
+
+		if five_hundred.score < 40_000 || five_hundred.time <= seven_min_30_sec {
+			return false;
+		}
+
+		if game_end.score < 126_000 || game_end.time <= thirteen_min_30_sec {
+			return false;
+		}
+
+		true
+	} else {
+		false
+	}
+}
+
+fn get_grade(score: i32, gm_requirements: &GMRequirements) -> Grade {
+	if is_gm(gm_requirements) {
+		return Grade::GM;
+	}
+
+	match score {
+		120_000.. => Grade::S9,
+		100_000.. => Grade::S8,
+		82_000.. => Grade::S7,
+		66_000.. => Grade::S6,
+		52_000.. => Grade::S5,
+		40_000.. => Grade::S4,
+		30_000.. => Grade::S3,
+		22_000.. => Grade::S2,
+		16_000.. => Grade::S1,
+
+		12_000.. => Grade::N1,
+		8_000.. => Grade::N2,
+		5_500.. => Grade::N3,
+		3_500.. => Grade::N4,
+		2_000.. => Grade::N5,
+		1_400.. => Grade::N6,
+		800.. => Grade::N7,
+		400.. => Grade::N8,
+		_ => Grade::N9,
+	}
+}
+
+impl GradingSystem for Tgm1Grading {
+	fn on_lock(&mut self, event: &GradeEvent) {
+		self.grade = get_grade(event.score, &event.gm_requirements);
+	}
+
+	fn grade(&self) -> Grade {
+		self.grade
+	}
+}
+
+// TAP Death: nothing until 500. getting there in time is M,
+// and 999 is GM.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DeathGrading {
+	grade: Grade,
+}
+
+impl Default for DeathGrading {
+	fn default() -> Self {
+		DeathGrading { grade: Grade::N9 }
+	}
+}
+
+impl GradingSystem for DeathGrading {
+	fn on_lock(&mut self, _event: &GradeEvent) {}
+
+	fn on_level(&mut self, old_level: i32, new_level: i32, frames: u32) {
+		let m = DEATH_TORIKAN;
+
+		if old_level < m.level && new_level >= m.level && frames <= m.frames {
+			self.grade = Grade::M;
+		}
+	}
+
+	fn on_game_clear(&mut self, _record: &PlayerRecord, _frames: u32) {
+		self.grade = Grade::GM;
+	}
+
+	fn grade(&self) -> Grade {
+		self.grade
+	}
+}
+
+// TGM3 Shirase: S1 at 100, and another S every 100 levels up to S13.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ShiraseGrading {
+	level: i32,
+}
+
+const SHIRASE_GRADES: [Grade; 14] = [
+	Grade::N1,
+	Grade::S1,
+	Grade::S2,
+	Grade::S3,
+	Grade::S4,
+	Grade::S5,
+	Grade::S6,
+	Grade::S7,
+	Grade::S8,
+	Grade::S9,
+	Grade::S10,
+	Grade::S11,
+	Grade::S12,
+	Grade::S13,
+];
+
+impl GradingSystem for ShiraseGrading {
+	fn on_lock(&mut self, _event: &GradeEvent) {}
+
+	fn on_level(&mut self, _old_level: i32, new_level: i32, _frames: u32) {
+		self.level = new_level;
+	}
+
+	fn grade(&self) -> Grade {
+		SHIRASE_GRADES[(self.level / 100).clamp(0, SHIRASE_GRADES.len() as i32 - 1) as usize]
+	}
+}
+
+// TAP master: clears earn grade points, 100 points goes up an internal grade,
+// and points slowly drain away while you aren't clearing.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TapGrading {
+	internal_grade: usize,
+	grade_points: i32,
+	decay_frames: i32,
+	// tetrises in each section, for the M requirements
+	tetrises: [i32; SECTION_COUNT],
+	// qualified for M at 999 (the arcade's orange line)
+	master: bool,
+	// then survived the invisible roll
+	grand_master: bool,
+}
+
+const TAP_INTERNAL_GRADES: usize = 32;
+
+// points for a single, double, triple and tetris, by internal grade.
+// everything from 10 up is the same.
+const TAP_GRADE_POINTS: [[i32; 4]; 11] = [
+	[10, 20, 40, 50],
+	[10, 20, 30, 40],
+	[10, 20, 30, 40],
+	[10, 15, 30, 40],
+	[10, 15, 20, 40],
+	[5, 15, 20, 30],
+	[5, 10, 20, 30],
+	[5, 10, 15, 30],
+	[5, 10, 15, 30],
+	[5, 10, 15, 30],
+	[2, 12, 13, 30],
+];
+
+// by combo (1 to 10+) and lines, in tenths.
+const TAP_COMBO_MULTIPLIERS: [[i32; 4]; 10] = [
+	[10, 10, 10, 10],
+	[10, 12, 14, 15],
+	[10, 12, 15, 18],
+	[10, 14, 16, 20],
+	[10, 14, 17, 22],
+	[10, 14, 18, 23],
+	[10, 14, 19, 24],
+	[10, 15, 20, 25],
+	[10, 15, 21, 26],
+	[10, 20, 25, 30],
+];
+
+// frames per point lost, by internal grade
+const TAP_DECAY_RATES: [i32; TAP_INTERNAL_GRADES] = [
+	125, 80, 80, 50, 45, 45, 45, 40, 40, 40, 40, 40, 30, 30, 30, 20, 20, 20, 20, 20, 15, 15, 15,
+	15, 15, 15, 15, 15, 15, 15, 10, 10,
+];
+
+// what each internal grade shows up as
+const TAP_DISPLAYED_GRADES: [Grade; TAP_INTERNAL_GRADES] = [
+	Grade::N9,
+	Grade::N8,
+	Grade::N7,
+	Grade::N6,
+	Grade::N5,
+	Grade::N5,
+	Grade::N4,
+	Grade::N4,
+	Grade::N4,
+	Grade::N3,
+	Grade::N3,
+	Grade::N2,
+	Grade::N2,
+	Grade::N2,
+	Grade::N1,
+	Grade::N1,
+	Grade::N1,
+	Grade::S1,
+	Grade::S1,
+	Grade::S2,
+	Grade::S3,
+	Grade::S4,
+	Grade::S4,
+	Grade::S4,
+	Grade::S5,
+	Grade::S5,
+	Grade::S6,
+	Grade::S6,
+	Grade::S7,
+	Grade::S7,
+	Grade::S8,
+	Grade::S9,
+];
+
+// M needs the first five sections in 1:05 each with two tetrises,
+// the last five no slower than 2 seconds over the first five's average
+// with a tetris each, S9 by 999, and 999 in under 8:45.
+const TAP_M_SECTION_TIME: u32 = 65 * FPS;
+const TAP_M_SLOWDOWN: u32 = 2 * FPS;
+const TAP_M_TOTAL_TIME: u32 = (8 * 60 + 45) * FPS;
+
+impl TapGrading {
+	fn points_for(&self, event: &GradeEvent) -> i32 {
+		let lines = event.lines.clamp(1, 4) as usize - 1;
+		let base = TAP_GRADE_POINTS[self.internal_grade.min(TAP_GRADE_POINTS.len() - 1)][lines];
+		let combo = TAP_COMBO_MULTIPLIERS[(event.combo.clamp(1, 10) - 1) as usize][lines];
+		let level = event.level_before / 250 + 1;
+
+		// rounded up, like the arcade
+		(base * combo + 9) / 10 * level
+	}
+
+	fn qualifies_for_master(&self, record: &PlayerRecord, frames: u32) -> bool {
+		let mut times = [0; SECTION_COUNT];
+		let mut last = 0;
+
+		for (time, split) in times.iter_mut().zip(record.sections) {
+			match split {
+				Some(split) => {
+					*time = split - last;
+					last = split;
+				}
+				None => return false,
+			}
+		}
+
+		let first_half = &times[..SECTION_COUNT / 2];
+		let average = first_half.iter().sum::<u32>() / first_half.len() as u32;
+
+		let first_half_ok = first_half
+			.iter()
+			.zip(self.tetrises)
+			.all(|(time, tetrises)| *time <= TAP_M_SECTION_TIME && tetrises >= 2);
+
+		let second_half_ok = times[SECTION_COUNT / 2..]
+			.iter()
+			.zip(&self.tetrises[SECTION_COUNT / 2..])
+			.all(|(time, tetrises)| *time <= average + TAP_M_SLOWDOWN && *tetrises >= 1);
+
+		first_half_ok
+			&& second_half_ok
+			&& self.internal_grade == TAP_INTERNAL_GRADES - 1
+			&& frames <= TAP_M_TOTAL_TIME
+	}
+}
+
+impl GradingSystem for TapGrading {
+	fn on_lock(&mut self, event: &GradeEvent) {
+		if event.lines <= 0 {
+			return;
+		}
+
+		if event.lines >= 4 {
+			let section = (event.level_before / 100) as usize;
+			self.tetrises[section.min(SECTION_COUNT - 1)] += 1;
+		}
+
+		self.grade_points += self.points_for(event);
+
+		if self.grade_points >= 100 {
+			self.grade_points = 0;
+			self.decay_frames = 0;
+			self.internal_grade = (self.internal_grade + 1).min(TAP_INTERNAL_GRADES - 1);
+		}
+	}
+
+	fn on_frame(&mut self, active: bool, combo: i32) {
+		// points only drain while there's a piece to move, and not mid-combo
+		if !active || combo > 1 || self.grade_points == 0 {
+			return;
+		}
+
+		self.decay_frames += 1;
+
+		if self.decay_frames >= TAP_DECAY_RATES[self.internal_grade] {
+			self.decay_frames = 0;
+			self.grade_points -= 1;
+		}
+	}
+
+	fn on_game_clear(&mut self, record: &PlayerRecord, frames: u32) {
+		self.master = self.qualifies_for_master(record, frames);
+	}
+
+	// an M goes for GM in the invisible roll, anyone else gets the fading one
+	fn roll(&self) -> Option<RollKind> {
+		Some(if self.master {
+			RollKind::Invisible
+		} else {
+			RollKind::Fading
+		})
+	}
+
+	fn on_roll_clear(&mut self, roll: RollKind) {
+		self.grand_master = self.master && roll == RollKind::Invisible;
+	}
+
+	fn grade(&self) -> Grade {
+		if self.grand_master {
+			Grade::GM
+		} else if self.master {
+			Grade::M
+		} else {
+			TAP_DISPLAYED_GRADES[self.internal_grade]
+		}
+	}
+}
+
+// TGM3 master: TAP's grade points, then every COOL is a grade up and every
+// REGRET a grade down. a COOL also makes the next section play 100 levels faster.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Tgm3Grading {
+	points: TapGrading,
+	judgements: [SectionJudgement; SECTION_COUNT],
+	// the frame the current section started on
+	section_start: u32,
+	// how long the last section took to reach x70
+	last_cool_time: Option<u32>,
+}
+
+// the most time each section can take to reach x70 and be COOL.
+// there's no COOL in the last section.
+const TGM3_COOL_TIMES: [u32; SECTION_COUNT - 1] = [52, 52, 49, 45, 45, 42, 42, 38, 38];
+// a COOL also can't be more than this much slower than the last section's x70
+const TGM3_COOL_SLOWDOWN: u32 = 2;
+// anything slower than this for a whole section is a REGRET
+const TGM3_REGRET_TIMES: [u32; SECTION_COUNT] = [90, 75, 75, 68, 60, 60, 50, 50, 50, 50];
+
+impl Tgm3Grading {
+	fn cools_before(&self, section: usize) -> i32 {
+		self.judgements
+			.iter()
+			.take(section)
+			.filter(|j| j.cool)
+			.count() as i32
+	}
+}
+
+impl GradingSystem for Tgm3Grading {
+	fn on_lock(&mut self, event: &GradeEvent) {
+		self.points.on_lock(event);
+	}
+
+	fn on_frame(&mut self, active: bool, combo: i32) {
+		self.points.on_frame(active, combo);
+	}
+
+	fn on_level(&mut self, old_level: i32, new_level: i32, frames: u32) {
+		let section = (old_level / 100) as usize;
+
+		if section >= SECTION_COUNT {
+			return;
+		}
+
+		let time = frames - self.section_start;
+		let cool_level = section as i32 * 100 + 70;
+
+		if old_level < cool_level && new_level >= cool_level && section < TGM3_COOL_TIMES.len() {
+			let fast_enough = time <= TGM3_COOL_TIMES[section] * FPS;
+			let kept_up = !matches!(
+				self.last_cool_time,
+				Some(last) if time > last + TGM3_COOL_SLOWDOWN * FPS
+			);
+
+			self.judgements[section].cool = fast_enough && kept_up;
+			self.last_cool_time = Some(time);
+		}
+
+		// 999 finishes the last section, and nothing after it counts
+		if old_level < 999 && (new_level >= 999 || new_level / 100 > old_level / 100) {
+			self.judgements[section].regret = time > TGM3_REGRET_TIMES[section] * FPS;
+			self.section_start = frames;
+		}
+	}
+
+	fn speed_levels(&self, level: i32) -> i32 {
+		let section = (level / 100).clamp(0, SECTION_COUNT as i32) as usize;
+
+		self.cools_before(section) * 100
+	}
+
+	fn judgements(&self) -> Option<&[SectionJudgement; SECTION_COUNT]> {
+		Some(&self.judgements)
+	}
+
+	fn grade(&self) -> Grade {
+		let grades: Vec<Grade> = Grade::iter().take_while(|g| *g != Grade::GM).collect();
+		let base = grades
+			.iter()
+			.position(|g| *g == self.points.grade())
+			.unwrap_or(0) as i32;
+
+		let cools = self.cools_before(SECTION_COUNT);
+		let regrets = self.judgements.iter().filter(|j| j.regret).count() as i32;
+
+		grades[(base + cools - regrets).clamp(0, grades.len() as i32 - 1) as usize]
+	}
+}
+
+// rows of staircase needed for a GM secret grade
+const SECRET_GRADE_ROWS: usize = 19;
+
+// TGM1's secret grade, for building a ">" out of holes. counting up from the
+// floor, each row has exactly one gap, stepping right from the left wall
+// and then back left again. every row in place is a grade: one row is a 9,
+// all 19 is GM.
+pub fn secret_grade(board: &Board) -> Option<Grade> {
+	let width = board.width();
+
+	// the grades only line up with a 10 wide board's staircase
+	if 2 * width - 1 != SECRET_GRADE_ROWS {
+		return None;
+	}
+
+	let rows = board
+		.state
+		.iter()
+		.take(SECRET_GRADE_ROWS)
+		.enumerate()
+		.take_while(|(y, row)| {
+			let gap = if *y < width { *y } else { 2 * (width - 1) - y };
+
+			row.iter()
+				.enumerate()
+				.all(|(x, s)| matches!(s, Square::Empty) == (x == gap))
+		})
+		.count();
+
+	match rows {
+		0 => None,
+		SECRET_GRADE_ROWS => Some(Grade::GM),
+		n => Grade::iter().nth(n - 1),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		rules::{GameSettings, RollKind},
+		GameState,
+	};
+
+	#[test]
+	fn clears_past_999_keep_the_last_regret() {
+		let mut tgm3 = Tgm3Grading::default();
+		let slow = (TGM3_REGRET_TIMES[SECTION_COUNT - 1] + 10) * FPS;
+
+		tgm3.on_level(998, 999, slow);
+		assert!(tgm3.judgements[SECTION_COUNT - 1].regret);
+
+		// two more clears, both quick
+		tgm3.on_level(999, 1001, slow + 1);
+		tgm3.on_level(1001, 1003, slow + 2);
+		assert!(tgm3.judgements[SECTION_COUNT - 1].regret);
+	}
+
+	#[test]
+	fn tap_picks_its_roll() {
+		let mut tap = TapGrading::default();
+		assert_eq!(tap.roll(), Some(RollKind::Fading));
+
+		tap.on_roll_clear(RollKind::Fading);
+		assert_ne!(tap.grade(), Grade::GM);
+
+		tap.master = true;
+		assert_eq!(tap.roll(), Some(RollKind::Invisible));
+
+		tap.on_roll_clear(RollKind::Invisible);
+		assert_eq!(tap.grade(), Grade::GM);
+	}
+
+	#[test]
+	fn tap_rolls_with_the_roll_setting_off() {
+		let settings = GameSettings {
+			grading: GradingKind::TapMaster,
+			roll: RollKind::Off,
+			..Default::default()
+		};
+		let mut game = GameState::new(settings, 1);
+
+		game.level = 998;
+		game.add_levels(1);
+
+		assert_eq!(game.roll.map(|r| r.kind), Some(RollKind::Fading));
+	}
+}
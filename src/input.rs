@@ -0,0 +1,91 @@
+// key and button events get buffered here between frames, instead of polling
+// once a frame. that way a tap that's over before the next frame still
+// happens, and so does letting go and pressing again in between frames.
+
+use std::collections::HashMap;
+
+use ggez::{input::gamepad::gilrs::Button, winit::event::VirtualKeyCode, Context};
+
+use crate::{
+	bindings::{Action, Bindings},
+	movement::{parse_movement, Movement},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+	Key(VirtualKeyCode),
+	Button(Button),
+}
+
+// everything is kept with when it was pressed, counted in presses,
+// so opposite directions can be settled by which came first.
+#[derive(Debug, Default)]
+pub struct InputState {
+	held: HashMap<Input, u64>,
+	// went down since the last frame was taken, whether or not it's still held.
+	pressed: HashMap<Input, u64>,
+	presses: u64,
+}
+
+impl InputState {
+	pub fn press(&mut self, input: Input) {
+		self.presses += 1;
+		self.held.insert(input, self.presses);
+		self.pressed.insert(input, self.presses);
+	}
+
+	pub fn release(&mut self, input: Input) {
+		self.held.remove(&input);
+	}
+
+	fn is_bound(bindings: &Bindings, action: Action, input: &Input) -> bool {
+		match input {
+			Input::Key(k) => bindings.keys(action).contains(k),
+			Input::Button(b) => bindings.pad.buttons(action).contains(b),
+		}
+	}
+
+	// this frame's movement, without using up the presses.
+	pub fn movement(&self, ctx: &Context, bindings: &Bindings) -> Movement {
+		// sticks don't tap, so they're still just polled.
+		let pads: Vec<_> = ctx.gamepad.gamepads().map(|(_, pad)| pad).collect();
+
+		let pressed = |action| {
+			self.pressed
+				.keys()
+				.any(|i| Self::is_bound(bindings, action, i))
+		};
+
+		// the latest press of anything bound to the action. sticks count as
+		// held forever, since there's no telling when they were pushed.
+		let held_since = |action| {
+			let stick = pads
+				.iter()
+				.any(|pad| bindings.pad.is_stick_held(action, pad))
+				.then_some(0);
+
+			self.held
+				.iter()
+				.chain(self.pressed.iter())
+				.filter(|(i, _)| Self::is_bound(bindings, action, i))
+				.map(|(_, at)| *at)
+				.chain(stick)
+				.max()
+		};
+
+		parse_movement(held_since, pressed, bindings.socd, bindings.diagonal)
+	}
+
+	// this frame's movement, and forget the presses that made it.
+	pub fn take_frame(&mut self, ctx: &Context, bindings: &Bindings) -> Movement {
+		let movement = self.movement(ctx, bindings);
+
+		self.end_frame();
+
+		movement
+	}
+
+	pub fn end_frame(&mut self) {
+		self.pressed.clear();
+	}
+}
@@ -0,0 +1,100 @@
+// the on-screen input display: what's held right now, how charged DAS is,
+// and a list of recent inputs with how long each was held.
+
+use std::collections::VecDeque;
+
+use ggez::{
+	glam::Vec2,
+	graphics::{self, Text},
+};
+
+use crate::movement::{AutoShift, Direction, Movement, Rotation};
+
+const HISTORY_LEN: usize = 16;
+const DAS_BAR_WIDTH: i32 = 16;
+
+// runs of the same input, newest first.
+#[derive(Debug, Clone, Default)]
+pub struct InputHistory {
+	runs: VecDeque<(Movement, u32)>,
+}
+
+impl InputHistory {
+	pub fn push(&mut self, movement: Movement) {
+		match self.runs.front_mut() {
+			// a fresh press is a new entry, even if it looks the same.
+			Some((last, count)) if same_held(last, &movement) && !is_new_press(&movement) => {
+				*count += 1
+			}
+			_ => {
+				self.runs.push_front((movement, 1));
+				self.runs.truncate(HISTORY_LEN);
+			}
+		}
+	}
+}
+
+fn same_held(a: &Movement, b: &Movement) -> bool {
+	a.horizontal == b.horizontal && a.vertical == b.vertical && a.rot == b.rot
+}
+
+fn is_new_press(movement: &Movement) -> bool {
+	movement.horizontal_pressed
+		|| movement.vertical_pressed
+		|| Rotation::PRIORITY
+			.into_iter()
+			.any(|r| movement.rot_pressed.get(r))
+}
+
+// e.g. "L D  A . C", with dots for buttons that aren't held.
+fn format_movement(movement: &Movement) -> String {
+	let dir = |d: Option<Direction>| match d {
+		Some(Direction::Left) => 'L',
+		Some(Direction::Right) => 'R',
+		Some(Direction::Up) => 'U',
+		Some(Direction::Down) => 'D',
+		None => '.',
+	};
+
+	let button = |rot, name| if movement.rot.get(rot) { name } else { '.' };
+
+	format!(
+		"{} {}  {} {} {}",
+		dir(movement.horizontal),
+		dir(movement.vertical),
+		button(Rotation::CCW, 'A'),
+		button(Rotation::CW, 'B'),
+		button(Rotation::CCW2, 'C'),
+	)
+}
+
+pub fn draw_inputs(
+	canvas: &mut graphics::Canvas,
+	movement: &Movement,
+	history: &InputHistory,
+	das_frames: i32,
+	shift: AutoShift,
+	pos: Vec2,
+) {
+	let charge = das_frames.clamp(0, shift.das);
+	let filled = if shift.das == 0 {
+		DAS_BAR_WIDTH
+	} else {
+		charge * DAS_BAR_WIDTH / shift.das
+	};
+
+	let mut text = format!(
+		"inputs\n{}\n\nDAS {:2}/{}\n[{}{}]\n\n",
+		format_movement(movement),
+		charge,
+		shift.das,
+		"#".repeat(filled as usize),
+		".".repeat((DAS_BAR_WIDTH - filled) as usize),
+	);
+
+	for (movement, frames) in history.runs.iter() {
+		text.push_str(&format!("{} {:>4}\n", format_movement(movement), frames));
+	}
+
+	canvas.draw(&Text::new(text), pos);
+}
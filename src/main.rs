@@ -1,47 +1,77 @@
+mod bindings;
 mod board;
+mod frontend;
+mod fumen;
+mod grading;
 mod gravity;
+mod input;
+mod input_display;
+mod medals;
 mod movement;
 mod piece;
+mod practice;
+mod randomiser;
+mod replay;
+mod results;
+mod rules;
+mod savestate;
 mod scoring;
+mod snapshot;
 
-use board::{Board, Square, BOARD_OFFSET_X, BOARD_OFFSET_Y, BOARD_SIZE, SQUARE_SIZE};
+use board::{
+	Board, BoardSize, Square, BOARD_OFFSET_X, BOARD_OFFSET_Y, DEFAULT_BOARD_SIZE, HIDDEN_RANGE,
+	SQUARE_SIZE, VISIBLE_RANGE, WIDTH_RANGE,
+};
 use core::panic;
+use frontend::{App, Options};
 use ggez::conf::{Conf, WindowSetup};
-use ggez::event::{self, EventHandler};
+use ggez::event;
 use ggez::glam::Vec2;
 use ggez::graphics::{DrawParam, Quad, Rect, Text};
-use ggez::{
-	graphics::{self, Color},
-	Context, ContextBuilder, GameResult,
-};
+use ggez::winit::event::VirtualKeyCode;
+use ggez::{graphics, Context, ContextBuilder};
+use grading::Grader;
+use input_display::{draw_inputs, InputHistory};
+use medals::Medals;
 use movement::{
-	apply_movement, fall, is_movement_legal, parse_movement, Direction, Movement, Rotation,
+	apply_movement, fall, find_twist, is_movement_legal, next_das_frames, sonic_drop, AutoShift,
+	Direction, LastMove, Movement, Rotation,
 };
 use piece::{Piece, PieceType};
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use scoring::{update_record, GMRequirements, Grade, PlayerRecord};
+use practice::PracticeConfig;
+use randomiser::Randomiser;
+use replay::Replay;
+use results::{format_frames, results_after_mono_frames};
+use rules::{GameSettings, RollKind, Timings, ROLL_FRAMES};
+use scoring::{update_record, GMRequirements, Grade, PlayerRecord, Scorer, MAX_SECTION_COUNT};
+use serde::{Deserialize, Serialize};
+use snapshot::{Snapshot, Snapshots, REWIND_SECONDS};
+use std::path::PathBuf;
 use std::time::Duration;
 use std::{collections::VecDeque, time::SystemTime};
 
 const FPS: u32 = 60;
-const ARE_FRAMES: i32 = 30;
 const DAS_FRAMES: i32 = 16;
-const LOCK_DELAY_FRAMES: i32 = 30;
-const LINE_CLEAR_FRAMES: i32 = 41;
+// 20hz auto-repeat
+const ARR_FRAMES: i32 = 3;
 
 // a piece is active and the player is moving it around
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct ActiveState {
 	piece: Piece,
 	lock_frames: i32,
 	das_frames: i32,
 	down_frames: i32,
 	gravity_frames: i32,
+	active_frames: i32,
+	rotations: i32,
+	// rows fallen from sonic drops
+	sonic_rows: i32,
+	last_move: LastMove,
 }
 
 // a piece has just been locked
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct WaitingState {
 	waiting_frames: i32,
 	das_frames: i32,
@@ -49,12 +79,30 @@ struct WaitingState {
 }
 
 // a piece has just been locked
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct GameOverState {
 	mono_frames: i32,
+	ending: Ending,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ending {
+	ToppedOut,
+	// got to this level too late
+	Torikan(i32),
+	// got to the max level, with no roll after it
+	Cleared,
+	// survived the credits
+	RollCleared(RollKind),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Roll {
+	kind: RollKind,
+	frames_left: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 enum State {
 	Active(ActiveState),
 	Waiting(WaitingState),
@@ -64,48 +112,44 @@ enum State {
 #[derive(Debug)]
 pub struct GameState {
 	state: State,
-	last_seen: VecDeque<PieceType>,
 	next_piece: PieceType,
+	// pieces that are dealt before the randomiser gets a say
+	forced_queue: VecDeque<PieceType>,
 
-	rng: ThreadRng,
+	randomiser: Randomiser,
+	settings: GameSettings,
 	level: i32,
 	current_combo: i32,
 
 	player_record: PlayerRecord,
+	grader: Grader,
+	scorer: Scorer,
+	medals: Medals,
+	// pieces without a clear since the last garbage row went in
+	garbage_pieces: i32,
 	run_start: SystemTime,
+	// frames since the game started
+	frames: u32,
+	// set when the game needs to end once this frame's done, like when a
+	// torikan was just reached too late
+	ending: Option<Ending>,
+	// the credit roll, once it's started
+	roll: Option<Roll>,
 
 	board: Board,
 	movement: Movement,
-}
-
-const TRIES: i32 = 4;
-
-fn draw_new_piece<R: Rng>(level: i32, rng: &mut R, last_seen: &VecDeque<PieceType>) -> PieceType {
-	let mut new_piece: Option<PieceType> = None;
-
-	// try X times to generate a piece we haven't seen before.
-	for _ in 0..TRIES {
-		let piece = PieceType::random(rng);
-		new_piece = Some(piece);
-
-		if !last_seen.contains(&piece) {
-			break;
-		}
-	}
-
-	let new_piece =
-		new_piece.expect("The constant TRIES was 0. No piece could ever be generated like this.");
-
-	// if this is the first piece we drew this game
-	// dont let it be S/Z/O.
-	// We do this recursively instead of a loop, that's probably fine. Right?
-	if level == 0
-		&& (new_piece == PieceType::S || new_piece == PieceType::Z || new_piece == PieceType::O)
-	{
-		return draw_new_piece(level, rng, last_seen);
-	}
-
-	new_piece
+	// every frame's input, for replays. None if we can't trust it,
+	// like after loading a savestate.
+	inputs: Option<Vec<Movement>>,
+	// inputs to play instead of the keyboard's
+	playback: Option<Vec<Movement>>,
+	// for the input display
+	input_history: InputHistory,
+
+	// set when playing practice mode, and what to go back to on a retry
+	practice: Option<PracticeConfig>,
+	snapshots: Snapshots,
+	savestate_path: PathBuf,
 }
 
 impl GameState {
@@ -113,36 +157,62 @@ impl GameState {
 		let current_next_piece = self.next_piece;
 
 		// add new element
-		self.last_seen.push_back(current_next_piece);
-		self.last_seen.pop_front();
+		self.randomiser.push_history(current_next_piece);
 
-		self.next_piece = draw_new_piece(self.level, &mut self.rng, &self.last_seen);
+		// practice queues go round and round
+		if let Some(practice) = &self.practice {
+			if self.forced_queue.is_empty() {
+				self.forced_queue.extend(practice.queue.iter());
+			}
+		}
+
+		self.next_piece = match self.forced_queue.pop_front() {
+			Some(p) => p,
+			None => self.randomiser.draw(),
+		};
 
-		// increase level if not at level stop (99, or 998)
-		if self.level != 998 && self.level % 100 != 99 {
-			self.level += 1;
+		// increase level if not at level stop (99, or the one before the max)
+		if self.level < self.settings.rules.max_level() - 1 && self.level % 100 != 99 {
+			self.add_levels(1);
 		}
 
 		let piece = current_next_piece.to_piece();
+		let piece = self
+			.board
+			.spawn(if self.is_big() { piece.to_big() } else { piece });
 
 		let rot = self.movement.rot;
 
-		// apply IRS if any
-		apply_movement(
+		// apply IRS if any. an IRS isn't a twist.
+		let (piece, _) = apply_movement(
 			&Movement::default(),
-			&Movement { rot, dir: None },
+			&Movement {
+				rot,
+				..Default::default()
+			},
 			0,
+			self.shift(),
 			piece,
 			&self.board,
-		)
+		);
+
+		piece
 	}
 }
 
 impl Default for GameState {
 	fn default() -> GameState {
-		let level = 500;
-		let mut rng = rand::thread_rng();
-		let last_seen = VecDeque::from([PieceType::Z, PieceType::Z, PieceType::Z, PieceType::Z]);
+		GameState::new(GameSettings::default(), rand::random())
+	}
+}
+
+impl GameState {
+	pub fn new(settings: GameSettings, seed: u32) -> GameState {
+		let level = settings.start_level;
+		let grader = Grader::new(settings.grading);
+		let board = Board::new(settings.board_size());
+		let scorer = Scorer::new(settings.scoring);
+		let mut randomiser = Randomiser::new(settings.randomiser, seed);
 
 		GameState {
 			state: State::Waiting(WaitingState {
@@ -150,12 +220,20 @@ impl Default for GameState {
 				did_clear_line: false,
 				das_frames: 0,
 			}),
-			next_piece: draw_new_piece(level, &mut rng, &last_seen),
-			last_seen,
-			rng,
+			next_piece: randomiser.draw(),
+			forced_queue: VecDeque::new(),
+			randomiser,
+			settings,
 			level,
 			current_combo: 0,
 			run_start: SystemTime::now(),
+			frames: 0,
+			ending: None,
+			roll: None,
+			grader,
+			scorer,
+			medals: Medals::default(),
+			garbage_pieces: 0,
 			player_record: PlayerRecord {
 				score: 0,
 				gm_requirements: GMRequirements {
@@ -165,228 +243,789 @@ impl Default for GameState {
 				},
 				start_time: SystemTime::now(),
 				grade: Grade::N9,
+				sections: [None; MAX_SECTION_COUNT],
+				lines: [0; 4],
 			},
-			board: Board::default(),
+			board,
 			movement: Movement::default(),
+			inputs: Some(vec![]),
+			playback: None,
+			input_history: InputHistory::default(),
+			practice: None,
+			snapshots: Snapshots::default(),
+			savestate_path: PathBuf::from(savestate::DEFAULT_SAVESTATE_PATH),
 		}
 	}
 }
 
 impl GameState {
-	pub fn new(_ctx: &mut Context) -> GameState {
-		GameState::default()
+	pub fn from_replay(replay: Replay) -> GameState {
+		GameState {
+			playback: Some(replay.inputs),
+			..GameState::new(replay.settings, replay.seed)
+		}
+	}
+
+	fn gravity(&self) -> i32 {
+		let speed_levels = self.grader.system().speed_levels(self.level);
+
+		self.settings.rules.gravity(self.level + speed_levels)
+	}
+
+	fn timings(&self) -> Timings {
+		let speed_levels = self.grader.system().speed_levels(self.level);
+
+		self.settings.rules.timings(self.level + speed_levels)
+	}
+
+	fn is_big(&self) -> bool {
+		self.settings.big || self.settings.rules.is_big(self.level)
+	}
+
+	// the player's DAS and ARR, unless the rules have their own DAS.
+	fn shift(&self) -> AutoShift {
+		AutoShift {
+			das: self.timings().das.unwrap_or(self.settings.shift.das),
+			..self.settings.shift
+		}
+	}
+
+	fn das_frames(&self) -> i32 {
+		match self.state {
+			State::Active(st) => st.das_frames,
+			State::Waiting(st) => st.das_frames,
+			State::GameOver(_) => 0,
+		}
+	}
+
+	// shirase pushes up a copy of the bottom row every so many pieces that
+	// don't clear anything. false if that pushed the stack out the top.
+	fn raise_garbage(&mut self, lines: i32) -> bool {
+		let Some(quota) = self.settings.rules.garbage_quota(self.level) else {
+			return true;
+		};
+
+		if self.roll.is_some() {
+			return true;
+		}
+
+		if lines > 0 {
+			return true;
+		}
+
+		self.garbage_pieces += 1;
+
+		if self.garbage_pieces < quota {
+			return true;
+		}
+
+		self.garbage_pieces = 0;
+
+		self.board.push_up(self.board.state[0].clone())
+	}
+
+	// moves the level on, keeping track of when each section was finished.
+	fn add_levels(&mut self, levels: i32) {
+		// practice keeps the same level forever, and the roll stays at the max
+		if self.practice.is_some() || self.roll.is_some() {
+			return;
+		}
+
+		let max_level = self.settings.rules.max_level();
+		let sections = self.settings.rules.section_count() as i32;
+		let old_level = self.level;
+		let old_section = self.level / 100;
+
+		self.level = (self.level + levels).min(max_level);
+
+		// the max level finishes the last section
+		let new_section = if self.level >= max_level {
+			sections
+		} else {
+			self.level / 100
+		};
+
+		for section in old_section..new_section.min(sections) {
+			self.player_record.sections[section as usize] = Some(self.frames);
+		}
+
+		self.grader
+			.system_mut()
+			.on_level(old_level, self.level, self.frames);
+		self.medals.on_level(old_level, self.level, self.frames);
+
+		let missed = self
+			.settings
+			.torikans
+			.iter()
+			.find(|t| old_level < t.level && self.level >= t.level && self.frames > t.frames);
+
+		if let Some(torikan) = missed {
+			self.ending = Some(Ending::Torikan(torikan.level));
+		}
+
+		if old_level < max_level && self.level >= max_level {
+			self.grader
+				.system_mut()
+				.on_game_clear(&self.player_record, self.frames);
+
+			// the stack goes away for the credits. TAP's grading picks its own roll.
+			let roll = self.grader.system().roll().unwrap_or(self.settings.roll);
+
+			if roll != RollKind::Off {
+				self.board = Board::new(self.board.size);
+				self.start_roll(roll);
+			} else if self.ending.is_none() {
+				self.ending = Some(Ending::Cleared);
+			}
+		}
+	}
+
+	// from here on, locked blocks vanish like the roll says.
+	fn start_roll(&mut self, kind: RollKind) {
+		self.board.vanish_after = kind.vanish_after();
+		self.roll = Some(Roll {
+			kind,
+			frames_left: ROLL_FRAMES,
+		});
+	}
+
+	// a fresh game of the same mode, keeping anything that isn't part of the game itself.
+	pub fn restart(&mut self, seed: u32) {
+		let savestate_path = self.savestate_path.clone();
+
+		*self = GameState::new(self.settings.clone(), seed);
+		self.savestate_path = savestate_path;
+	}
+
+	// pause's retry. practice goes back to how it started, anything else is
+	// a new game of the same mode.
+	pub fn retry(&mut self) {
+		if self.practice.is_some() {
+			self.retry_practice();
+		} else {
+			self.restart(rand::random());
+		}
+	}
+
+	pub fn seed(&self) -> u32 {
+		self.randomiser.seed
+	}
+
+	pub fn is_showing_results(&self) -> bool {
+		let after = results_after_mono_frames(&self.board);
+
+		matches!(self.state, State::GameOver(st) if st.mono_frames >= after)
+	}
+
+	pub fn save_replay(&self) {
+		let Some(inputs) = &self.inputs else {
+			eprintln!("this game can't be replayed, a savestate was loaded during it");
+			return;
+		};
+
+		let path = PathBuf::from(format!("rgm-{}.replay", self.randomiser.seed));
+
+		let replay = Replay {
+			settings: self.settings.clone(),
+			seed: self.randomiser.seed,
+			inputs: inputs.clone(),
+		};
+
+		match replay::save(&path, &replay) {
+			Ok(()) => println!("saved replay to {}", path.display()),
+			Err(err) => eprintln!("{}", err),
+		}
+	}
+
+	pub fn practice(config: PracticeConfig) -> GameState {
+		let mut state = GameState {
+			level: config.level,
+			board: config.board.clone(),
+			forced_queue: config.queue.iter().copied().collect(),
+			..Default::default()
+		};
+
+		if let Some(p) = state.forced_queue.pop_front() {
+			state.next_piece = p;
+		}
+
+		if config.roll != RollKind::Off {
+			state.start_roll(config.roll);
+		}
+
+		state.practice = Some(config);
+		state.snapshots.push(Snapshot::take(&state));
+
+		state
+	}
+
+	// puts a practice game back how it started.
+	fn retry_practice(&mut self) {
+		if let Some(config) = self.practice.take() {
+			let savestate_path = self.savestate_path.clone();
+
+			*self = GameState::practice(config);
+			self.savestate_path = savestate_path;
+		}
+	}
+
+	fn undo_piece(&mut self) {
+		if let Some(snapshot) = self.snapshots.undo().cloned() {
+			snapshot.restore(self);
+		}
+	}
+
+	fn rewind(&mut self, seconds: u32) {
+		if let Some(snapshot) = self.snapshots.rewind(self.frames, seconds).cloned() {
+			snapshot.restore(self);
+		}
+	}
+
+	fn save_state(&self) {
+		match savestate::save(
+			&self.savestate_path,
+			self.settings.clone(),
+			Snapshot::take(self),
+		) {
+			Ok(()) => println!("saved state to {}", self.savestate_path.display()),
+			Err(err) => eprintln!("{}", err),
+		}
+	}
+
+	fn load_state(&mut self) {
+		match savestate::load(&self.savestate_path) {
+			Ok((settings, snapshot)) => {
+				self.settings = settings;
+				snapshot.restore(self);
+
+				// we don't know what happened before this
+				self.inputs = None;
+
+				// undo shouldn't go back past a load
+				self.snapshots = Snapshots::default();
+				self.snapshots.push(snapshot);
+			}
+			Err(err) => eprintln!("{}", err),
+		}
+	}
+
+	pub fn to_fumen(&self) -> String {
+		let mut queue = vec![self.next_piece];
+		queue.extend(self.forced_queue.iter());
+
+		let piece = match self.state {
+			State::Active(a) => Some(a.piece),
+			_ => None,
+		};
+
+		fumen::encode(&self.board, piece, &queue)
 	}
 }
 
-impl EventHandler for GameState {
-	fn update(&mut self, ctx: &mut Context) -> GameResult {
-		while ctx.time.check_update_time(FPS) {
-			let previous_movement = self.movement;
+impl GameState {
+	// keys that aren't part of playing the game, checked once per rendered frame.
+	pub fn handle_hotkeys(&mut self, ctx: &mut Context) {
+		if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::E) {
+			println!("{}", self.to_fumen());
+		}
 
-			self.movement = parse_movement(ctx);
+		if self.practice.is_some() {
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::R) {
+				self.retry_practice();
+			}
 
-			self.state = match self.state {
-				State::Active(mut st) => {
-					// if this piece spawned in illegal, you're dead
-					if !is_movement_legal(&st.piece, &self.board) {
-						State::GameOver(GameOverState { mono_frames: 0 })
-					} else {
-						// initial fall: todo investigate why
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::U) {
+				self.undo_piece();
+			}
 
-						if let Some(next_st) =
-							fall(st.piece, &self.board, self.level, st.gravity_frames)
-						{
-							st.piece = next_st;
-						}
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::Back) {
+				self.rewind(REWIND_SECONDS);
+			}
 
-						// move piece
-						st.piece = apply_movement(
-							&previous_movement,
-							&self.movement,
-							st.das_frames,
-							st.piece,
-							&self.board,
-						);
-
-						match fall(st.piece, &self.board, self.level, st.gravity_frames) {
-							Some(next_st) => {
-								// piece is not on the floor
-								st.lock_frames = 0;
-
-								// this piece either didn't need to fall, or fell successfully.
-								if next_st.y == st.piece.y {
-									// didn't need to fall
-									// frames since last gravity application increases
-									st.gravity_frames += 1;
-								} else {
-									// frames since last grav application resets
-									st.gravity_frames = 1;
-								}
-
-								st.piece = next_st;
-							}
-							None => {
-								// piece is on the floor
-								st.lock_frames += 1;
-							}
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::F5) {
+				self.save_state();
+			}
+
+			if ctx.keyboard.is_key_just_pressed(VirtualKeyCode::F9) {
+				self.load_state();
+			}
+		}
+	}
+
+	// runs one frame of the game with this frame's input.
+	// a replay being played back ignores it.
+	pub fn tick(&mut self, movement: Movement) {
+		let previous_movement = self.movement;
+
+		self.movement = match &self.playback {
+			Some(inputs) => inputs
+				.get(self.frames as usize)
+				.copied()
+				.unwrap_or_default(),
+			None => movement,
+		};
+
+		// the clock stops when the game's over
+		if !matches!(self.state, State::GameOver(_)) {
+			if let Some(inputs) = &mut self.inputs {
+				inputs.push(self.movement);
+			}
+
+			self.input_history.push(self.movement);
+			self.frames += 1;
+			self.board.tick_vanish();
+
+			if let Some(roll) = &mut self.roll {
+				roll.frames_left = roll.frames_left.saturating_sub(1);
+			}
+
+			self.grader
+				.system_mut()
+				.on_frame(matches!(self.state, State::Active(_)), self.current_combo);
+		}
+
+		let mut locked = false;
+
+		self.state = match self.state {
+			State::Active(mut st) => {
+				// if this piece spawned in illegal, you're dead
+				if !is_movement_legal(&st.piece, &self.board) {
+					State::GameOver(GameOverState {
+						mono_frames: 0,
+						ending: Ending::ToppedOut,
+					})
+				} else {
+					// initial fall: todo investigate why
+
+					if let Some(next_st) =
+						fall(st.piece, &self.board, self.gravity(), st.gravity_frames)
+					{
+						st.piece = next_st;
+					}
+
+					st.das_frames =
+						next_das_frames(st.das_frames, &previous_movement, &self.movement);
+
+					// move piece
+					let (piece, last_move) = apply_movement(
+						&previous_movement,
+						&self.movement,
+						st.das_frames,
+						self.shift(),
+						st.piece,
+						&self.board,
+					);
+
+					st.piece = piece;
+					st.active_frames += 1;
+
+					if let Some(last_move) = last_move {
+						st.last_move = last_move;
+
+						if last_move != LastMove::Shift {
+							st.rotations += 1;
 						}
+					}
 
-						match self.movement.dir {
-							// down increases down frames
-							Some(Direction::Down) => st.down_frames += 1,
-							// repeated holds in the same direction update DAS
-							Some(Direction::Left) | Some(Direction::Right) => {
-								if previous_movement.dir == self.movement.dir {
-									st.das_frames += 1;
-								} else {
-									st.das_frames = 0;
-								}
-							}
-							_ => st.das_frames = 0,
+					// up sonic drops
+					if self.movement.vertical == Some(Direction::Up) {
+						let dropped = sonic_drop(st.piece, &self.board);
+						let scale = if dropped.big { 2 } else { 1 };
+
+						if dropped.y != st.piece.y {
+							st.sonic_rows += (st.piece.y - dropped.y) * scale;
+							st.last_move = LastMove::Shift;
 						}
 
-						if (st.lock_frames >= LOCK_DELAY_FRAMES)
-							|| (st.lock_frames > 0 && self.movement.dir == Some(Direction::Down))
-						{
-							let lines = self.board.lock_piece(st.piece);
+						st.piece = dropped;
+					}
+
+					match fall(st.piece, &self.board, self.gravity(), st.gravity_frames) {
+						Some(next_st) => {
+							// piece is not on the floor
+							st.lock_frames = 0;
 
-							if lines > 0 {
-								self.current_combo += 1;
+							// this piece either didn't need to fall, or fell successfully.
+							if next_st.y == st.piece.y {
+								// didn't need to fall
+								// frames since last gravity application increases
+								st.gravity_frames += 1;
 							} else {
-								self.current_combo = 0;
+								// frames since last grav application resets
+								st.gravity_frames = 1;
+								st.last_move = LastMove::Shift;
 							}
 
-							update_record(self, lines, st.down_frames);
+							st.piece = next_st;
+						}
+						None => {
+							// piece is on the floor
+							st.lock_frames += 1;
+						}
+					}
+
+					// down increases down frames
+					if self.movement.vertical == Some(Direction::Down) {
+						st.down_frames += 1;
+					}
+
+					if (st.lock_frames >= self.timings().lock_delay)
+						|| (st.lock_frames > 0 && self.movement.vertical == Some(Direction::Down))
+					{
+						let twist = find_twist(st.piece, st.last_move, &self.board);
+						let lines = self.board.lock_piece(st.piece);
+
+						if lines > 0 {
+							self.current_combo += 1;
+						} else {
+							self.current_combo = 0;
+						}
+
+						update_record(self, lines, &st, twist);
 
+						locked = true;
+
+						if !self.raise_garbage(lines) {
+							State::GameOver(GameOverState {
+								mono_frames: 0,
+								ending: Ending::ToppedOut,
+							})
+						} else {
 							// piece needs to lock
+							// DAS stays charged through ARE and line clears
 							State::Waiting(WaitingState {
 								waiting_frames: 0,
-								das_frames: 0,
-								// cba
-								did_clear_line: false,
+								das_frames: st.das_frames,
+								did_clear_line: lines > 0,
 							})
-						} else {
-							State::Active(st)
 						}
+					} else {
+						State::Active(st)
 					}
 				}
+			}
 
-				State::Waiting(mut st) => {
-					match self.movement.dir {
-						// repeated holds in the same direction update DAS
-						Some(Direction::Left) | Some(Direction::Right) => {
-							if previous_movement.dir == self.movement.dir {
-								st.das_frames += 1;
-							} else {
-								st.das_frames = 0;
-							}
-						}
-						_ => st.das_frames = 0,
-					}
-
-					if (st.did_clear_line && st.waiting_frames >= LINE_CLEAR_FRAMES)
-						|| st.waiting_frames >= ARE_FRAMES
-					{
-						// go into playable state
+			State::Waiting(mut st) => {
+				st.das_frames = next_das_frames(st.das_frames, &previous_movement, &self.movement);
 
-						let mut piece = self.iter_piece();
+				let timings = self.timings();
+				let wait = if st.did_clear_line {
+					timings.line_clear + timings.line_are
+				} else {
+					timings.are
+				};
 
-						// always perform a fall on the first frame.
-						if let Some(n_piece) = fall(piece, &self.board, self.level, 1) {
-							piece = n_piece;
-						}
+				if st.waiting_frames >= wait {
+					// go into playable state
 
-						State::Active(ActiveState {
-							piece,
-							lock_frames: 0,
-							das_frames: st.das_frames,
-							down_frames: 0,
-							gravity_frames: 0,
-						})
-					} else {
-						st.waiting_frames += 1;
+					let mut piece = self.iter_piece();
 
-						State::Waiting(st)
+					// always perform a fall on the first frame.
+					if let Some(n_piece) = fall(piece, &self.board, self.gravity(), 1) {
+						piece = n_piece;
 					}
+
+					State::Active(ActiveState {
+						piece,
+						lock_frames: 0,
+						das_frames: st.das_frames,
+						down_frames: 0,
+						gravity_frames: 0,
+						active_frames: 0,
+						rotations: 0,
+						sonic_rows: 0,
+						last_move: LastMove::Shift,
+					})
+				} else {
+					st.waiting_frames += 1;
+
+					State::Waiting(st)
 				}
+			}
+
+			// practice has infinite retries
+			State::GameOver(_) if self.practice.is_some() => {
+				self.retry_practice();
 
-				State::GameOver(mut st) => {
-					st.mono_frames += 1;
+				self.state
+			}
 
-					self.board.monoify(st.mono_frames);
+			State::GameOver(mut st) => {
+				st.mono_frames += 1;
 
-					State::GameOver(st)
-				}
+				// the stack comes back to be looked at
+				self.board.reveal();
+				self.board.monoify(st.mono_frames);
+
+				State::GameOver(st)
+			}
+		};
+
+		// the game stops right at the torikan, or the max level
+		if let Some(ending) = self.ending.take() {
+			if let Ending::Torikan(level) = ending {
+				self.level = level;
+			}
+
+			self.state = State::GameOver(GameOverState {
+				mono_frames: 0,
+				ending,
+			});
+		}
+
+		// the game ends when the credits do
+		if let Some(roll) = self.roll {
+			if roll.frames_left == 0 && !matches!(self.state, State::GameOver(_)) {
+				self.grader.system_mut().on_roll_clear(roll.kind);
+				self.player_record.grade = self.grader.system().grade();
+				self.state = State::GameOver(GameOverState {
+					mono_frames: 0,
+					ending: Ending::RollCleared(roll.kind),
+				});
 			}
 		}
 
-		Ok(())
+		if locked && self.practice.is_some() {
+			self.snapshots.push(Snapshot::take(self));
+		}
+	}
+
+	pub fn draw_board_frame(&self, canvas: &mut graphics::Canvas) {
+		self.board.draw(canvas);
 	}
 
-	fn draw(&mut self, ctx: &mut Context) -> GameResult {
-		let mut canvas = graphics::Canvas::from_frame(ctx, Color::BLACK);
+	pub fn draw(&self, canvas: &mut graphics::Canvas, options: &Options) {
+		self.draw_board_frame(canvas);
 
-		self.board.draw(&mut canvas);
+		let mono = self.settings.rules.is_monochrome(self.level);
 
 		// next box
-		let next_box = self.next_piece.to_piece().get_box();
+		if options.show_next {
+			let next_box = self.next_piece.to_piece().get_box();
+			let next_box = if mono { next_box.mono() } else { next_box };
+
+			next_box.draw(
+				canvas,
+				BOARD_OFFSET_X + SQUARE_SIZE * (self.board.width() as f32 / 3.0),
+				BOARD_OFFSET_Y - SQUARE_SIZE * 3.25,
+			);
+		}
 
-		next_box.draw(
-			&mut canvas,
-			BOARD_OFFSET_X + SQUARE_SIZE * (BOARD_SIZE.0 as f32 / 3.0),
-			BOARD_OFFSET_Y - SQUARE_SIZE * 3.25,
-		);
+		// board state. the row just above the frame shows too.
+		let shown = (self.board.size.visible + 1).min(self.board.height());
 
-		// board state
-		let mut x: f32 = BOARD_OFFSET_X;
-		let mut y: f32 = BOARD_OFFSET_Y + SQUARE_SIZE * (BOARD_SIZE.1 - 2) as f32;
-		for row in self.board.state {
-			for square in row {
-				square.draw(&mut canvas, x, y);
+		for y in 0..shown {
+			for x in 0..self.board.width() {
+				let square = self.board.shown(x, y);
+				let square = if mono { square.mono() } else { square };
 
-				x += SQUARE_SIZE;
+				square.draw(
+					canvas,
+					BOARD_OFFSET_X + SQUARE_SIZE * x as f32,
+					self.board.row_y(y as i32),
+				);
 			}
-
-			x = BOARD_OFFSET_X;
-			y -= SQUARE_SIZE;
 		}
 
+		self.board.draw_outline(canvas);
+
 		canvas.draw(
 			&Text::new(format!("level {}", self.level)),
 			Vec2::new(400., 400.),
 		);
+
+		if let Some(roll) = self.roll {
+			canvas.draw(
+				&Text::new(format!("roll {}", format_frames(roll.frames_left))),
+				Vec2::new(400., 420.),
+			);
+		}
 		canvas.draw(
 			&Text::new(format!("grade {}", self.player_record.grade)),
 			Vec2::new(400., 200.),
 		);
 
+		// the latest COOL or REGRET
+		if let Some(judgements) = self.grader.system().judgements() {
+			let latest = judgements
+				.iter()
+				.enumerate()
+				.rev()
+				.find(|(_, j)| j.cool || j.regret);
+
+			if let Some((section, judgement)) = latest {
+				canvas.draw(
+					&Text::new(format!("{:03} {}", section * 100, judgement)),
+					Vec2::new(400., 230.),
+				);
+			}
+		}
+
 		canvas.draw(
 			&Text::new(format!("score\n{}", self.player_record.score)),
 			Vec2::new(400., 300.),
 		);
 
-		if let State::Active(a) = self.state {
+		let medals: Vec<String> = self
+			.medals
+			.earned()
+			.iter()
+			.map(|(name, medal)| format!("{} {}", name, medal))
+			.collect();
+
+		canvas.draw(&Text::new(medals.join("\n")), Vec2::new(20., 100.));
+
+		if self.practice.is_some() {
 			canvas.draw(
-				&Text::new(format!("{:#?}", LOCK_DELAY_FRAMES - a.lock_frames)),
-				Vec2::new(400., 100.),
+				&Text::new(
+					"practice\nR to retry\nU to undo\nBackspace to rewind\nF5 to save\nF9 to load",
+				),
+				Vec2::new(400., 450.),
 			);
+		}
 
-			// current piece
-			a.piece.get_box().draw(
-				&mut canvas,
-				BOARD_OFFSET_X + SQUARE_SIZE * a.piece.x as f32,
-				BOARD_OFFSET_Y + SQUARE_SIZE * (BOARD_SIZE.1 as i32 - 1 - a.piece.y) as f32,
+		if options.show_inputs {
+			draw_inputs(
+				canvas,
+				&self.movement,
+				&self.input_history,
+				self.das_frames(),
+				self.shift(),
+				Vec2::new(600., 60.),
 			);
 		}
 
-		canvas.finish(ctx)
+		if let State::Active(a) = self.state {
+			if options.show_lock_delay {
+				canvas.draw(
+					&Text::new(format!("{:#?}", self.timings().lock_delay - a.lock_frames)),
+					Vec2::new(400., 100.),
+				);
+			}
+
+			// current piece, square by square so big pieces come out big
+			let square = Square::Filled(a.piece.p_type.get_color());
+			let square = if mono { square.mono() } else { square };
+
+			for (x, y) in a.piece.cells() {
+				square.draw(
+					canvas,
+					BOARD_OFFSET_X + SQUARE_SIZE * x as f32,
+					self.board.row_y(y),
+				);
+			}
+		}
+	}
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Result<Option<&'a String>, String> {
+	match args.iter().position(|a| a == name) {
+		Some(idx) => match args.get(idx + 1) {
+			Some(v) => Ok(Some(v)),
+			None => Err(format!("{} needs a value after it", name)),
+		},
+		None => Ok(None),
+	}
+}
+
+// --practice, --fumen <code>, --queue <pieces>, --level <n> and --roll <kind>
+// all start a practice game, on the board from the command line if any.
+fn practice_from_args(
+	args: &[String],
+	board: Option<BoardSize>,
+) -> Result<Option<PracticeConfig>, String> {
+	let fumen = arg_value(args, "--fumen")?;
+	let queue = arg_value(args, "--queue")?;
+	let level = arg_value(args, "--level")?;
+	let roll = arg_value(args, "--roll")?;
+
+	if fumen.is_none()
+		&& queue.is_none()
+		&& level.is_none()
+		&& roll.is_none()
+		&& !args.iter().any(|a| a == "--practice")
+	{
+		return Ok(None);
+	}
+
+	let mut config = match fumen {
+		Some(code) => match fumen::decode(code) {
+			Ok(pages) if !pages.is_empty() => PracticeConfig::from_fumen(pages[0].clone()),
+			Ok(_) => return Err("that fumen has no pages in it".to_string()),
+			Err(err) => return Err(format!("couldn't load fumen: {}", err)),
+		},
+		None => PracticeConfig::default(),
+	};
+
+	// a fumen page is always on the default board
+	if let Some(size) = board {
+		if fumen.is_some() {
+			return Err("a fumen's board can't be resized".to_string());
+		}
+
+		config.board = Board::new(size);
+	}
+
+	if let Some(queue) = queue {
+		config.queue = practice::parse_queue(queue)?;
+	}
+
+	if let Some(level) = level {
+		config.level = level
+			.parse()
+			.map_err(|_| format!("'{}' is not a level", level))?;
+	}
+
+	if let Some(roll) = roll {
+		config.roll = practice::parse_roll(roll)?;
+	}
+
+	Ok(Some(config))
+}
+
+// --width <n>, --height <n> and --hidden <n> size the board, from the
+// default one. None if none of them were given.
+fn board_from_args(args: &[String]) -> Result<Option<BoardSize>, String> {
+	let mut size = DEFAULT_BOARD_SIZE;
+	let mut given = false;
+
+	for (name, value) in [
+		("--width", &mut size.width),
+		("--height", &mut size.visible),
+		("--hidden", &mut size.hidden),
+	] {
+		if let Some(v) = arg_value(args, name)? {
+			*value = v
+				.parse()
+				.map_err(|_| format!("'{}' is not a number of squares", v))?;
+			given = true;
+		}
+	}
+
+	if !given {
+		return Ok(None);
+	}
+
+	if !size.is_playable() {
+		return Err(format!(
+			"the board can be {} to {} wide, {} to {} high, with {} to {} hidden rows",
+			WIDTH_RANGE.start(),
+			WIDTH_RANGE.end(),
+			VISIBLE_RANGE.start(),
+			VISIBLE_RANGE.end(),
+			HIDDEN_RANGE.start(),
+			HIDDEN_RANGE.end()
+		));
 	}
+
+	Ok(Some(size))
 }
 
 fn main() {
 	unsafe { backtrace_on_stack_overflow::enable() };
 
-	let (mut ctx, event_loop) = ContextBuilder::new("RGM", "zkldi")
+	let (ctx, event_loop) = ContextBuilder::new("RGM", "zkldi")
 		.default_conf(Conf {
 			window_setup: WindowSetup {
 				title: "RGM".to_string(),
@@ -400,8 +1039,111 @@ fn main() {
 	// Create an instance of your event handler.
 	// Usually, you should provide it with the Context object to
 	// use when setting your game up.
-	let state = GameState::new(&mut ctx);
+	let args: Vec<String> = std::env::args().collect();
+
+	let board = match board_from_args(&args) {
+		Ok(board) => board,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+
+	let mut state = match practice_from_args(&args, board) {
+		Ok(Some(config)) => Some(GameState::practice(config)),
+		Ok(None) => None,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+
+	match arg_value(&args, "--replay") {
+		Ok(Some(path)) => match replay::load(&PathBuf::from(path)) {
+			Ok(replay) => state = Some(GameState::from_replay(replay)),
+			Err(err) => {
+				eprintln!("{}", err);
+				std::process::exit(1);
+			}
+		},
+		Ok(None) => (),
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	}
+
+	match arg_value(&args, "--savestate") {
+		Ok(Some(path)) => {
+			if let Some(state) = &mut state {
+				state.savestate_path = PathBuf::from(path);
+			}
+		}
+		Ok(None) => (),
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	}
+
+	// anything from the command line skips straight to playing it
+	let app = match (state, board) {
+		(Some(state), _) => App::playing(state),
+		(None, Some(board)) => App::new().with_board(board),
+		(None, None) => App::new(),
+	};
 
 	// Run!
-	event::run(ctx, event_loop, state);
+	event::run(ctx, event_loop, app);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(s: &str) -> Vec<String> {
+		s.split_whitespace().map(String::from).collect()
+	}
+
+	#[test]
+	fn board_size_from_args() {
+		assert_eq!(board_from_args(&args("rgm")), Ok(None));
+		assert_eq!(
+			board_from_args(&args("rgm --height 20 --hidden 20")),
+			Ok(Some(BoardSize {
+				hidden: 20,
+				..DEFAULT_BOARD_SIZE
+			}))
+		);
+		assert_eq!(
+			board_from_args(&args("rgm --width 4")),
+			Ok(Some(BoardSize {
+				width: 4,
+				..DEFAULT_BOARD_SIZE
+			}))
+		);
+
+		assert!(board_from_args(&args("rgm --width 11")).is_err());
+		assert!(board_from_args(&args("rgm --hidden 0")).is_err());
+		assert!(board_from_args(&args("rgm --height tall")).is_err());
+	}
+
+	#[test]
+	fn practice_plays_on_the_board_from_args() {
+		let size = BoardSize {
+			width: 4,
+			..DEFAULT_BOARD_SIZE
+		};
+
+		let config = practice_from_args(&args("rgm --practice"), Some(size))
+			.unwrap()
+			.unwrap();
+		assert_eq!(config.board.size, size);
+
+		let code = fumen::encode(&Board::default(), None, &[]);
+		assert_eq!(
+			practice_from_args(&args(&format!("rgm --fumen {}", code)), Some(size)).err(),
+			Some("a fumen's board can't be resized".to_string())
+		);
+	}
 }
@@ -0,0 +1,136 @@
+// TAP's medals. bronze, silver and gold for all clears (AC), section times (ST),
+// tetrises (SK), digging out of a tall stack (RE), rotating (RO) and combos (CO).
+
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::{scoring::LockEvent, FPS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Display)]
+pub enum Medal {
+	#[strum(serialize = "bronze")]
+	Bronze,
+	#[strum(serialize = "silver")]
+	Silver,
+	#[strum(serialize = "gold")]
+	Gold,
+}
+
+// what it takes for bronze, silver and gold
+const AC_BRAVOS: [i32; 3] = [1, 2, 3];
+const SK_TETRISES: [i32; 3] = [10, 20, 35];
+const RE_RECOVERIES: [i32; 3] = [1, 2, 4];
+const CO_COMBO: [i32; 3] = [4, 5, 7];
+// the most seconds a section can take
+const ST_SECONDS: [u32; 3] = [60, 50, 45];
+// average rotations per piece, in tenths
+const RO_ROTATIONS: [i32; 3] = [12, 14, 17];
+
+// a stack of this many blocks or more needs recovering from,
+// and it's recovered once it's this small again.
+const RE_TALL_STACK: usize = 150;
+const RE_RECOVERED: usize = 70;
+
+// RO is checked every this many pieces, and a piece only counts
+// for so many rotations.
+const RO_PIECES: i32 = 50;
+const RO_MAX_ROTATIONS: i32 = 4;
+
+fn medal_for(count: i32, [bronze, silver, gold]: [i32; 3]) -> Option<Medal> {
+	if count >= gold {
+		Some(Medal::Gold)
+	} else if count >= silver {
+		Some(Medal::Silver)
+	} else if count >= bronze {
+		Some(Medal::Bronze)
+	} else {
+		None
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Medals {
+	bravos: i32,
+	tetrises: i32,
+	recoveries: i32,
+	// the stack has been tall, and hasn't come back down yet
+	recovering: bool,
+	best_combo: i32,
+	// the frame the current section started on
+	section_start: u32,
+	st: Option<Medal>,
+	// since the last RO check
+	rotations: i32,
+	pieces: i32,
+	ro: Option<Medal>,
+}
+
+impl Medals {
+	// blocks is how many squares are filled once the piece is locked and cleared.
+	pub fn on_lock(&mut self, event: &LockEvent, blocks: usize) {
+		if event.bravo {
+			self.bravos += 1;
+		}
+
+		if event.lines == 4 {
+			self.tetrises += 1;
+		}
+
+		if blocks >= RE_TALL_STACK {
+			self.recovering = true;
+		} else if self.recovering && blocks <= RE_RECOVERED {
+			self.recovering = false;
+			self.recoveries += 1;
+		}
+
+		self.best_combo = self.best_combo.max(event.combo);
+
+		// the arcade's RO is for rotating a lot, not a little
+		self.rotations += event.rotations.min(RO_MAX_ROTATIONS);
+		self.pieces += 1;
+
+		if self.pieces == RO_PIECES {
+			let average = self.rotations * 10 / self.pieces;
+
+			self.ro = self.ro.max(medal_for(average, RO_ROTATIONS));
+			self.rotations = 0;
+			self.pieces = 0;
+		}
+	}
+
+	pub fn on_level(&mut self, old_level: i32, new_level: i32, frames: u32) {
+		// 999 finishes the last section
+		if (old_level < 999 && new_level >= 999) || new_level / 100 > old_level / 100 {
+			let seconds = (frames - self.section_start) / FPS;
+
+			// the limits go bronze, silver, gold, so check them backwards
+			let st = if seconds <= ST_SECONDS[2] {
+				Some(Medal::Gold)
+			} else if seconds <= ST_SECONDS[1] {
+				Some(Medal::Silver)
+			} else if seconds <= ST_SECONDS[0] {
+				Some(Medal::Bronze)
+			} else {
+				None
+			};
+
+			self.st = self.st.max(st);
+			self.section_start = frames;
+		}
+	}
+
+	// the ones earned so far, in the arcade's order.
+	pub fn earned(&self) -> Vec<(&'static str, Medal)> {
+		[
+			("AC", medal_for(self.bravos, AC_BRAVOS)),
+			("ST", self.st),
+			("SK", medal_for(self.tetrises, SK_TETRISES)),
+			("RE", medal_for(self.recoveries, RE_RECOVERIES)),
+			("RO", self.ro),
+			("CO", medal_for(self.best_combo, CO_COMBO)),
+		]
+		.into_iter()
+		.filter_map(|(name, medal)| medal.map(|m| (name, m)))
+		.collect()
+	}
+}
@@ -1,15 +1,16 @@
 // without the bother of all of the meaning
 
-use ggez::{winit::event::VirtualKeyCode, Context};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
 
 use crate::{
-	board::{Board, Square, BOARD_OFFSET_X, BOARD_OFFSET_Y, BOARD_SIZE},
-	gravity::{get_gravity, grav_to_rpf},
+	bindings::Action,
+	board::{Board, Square, BOARD_OFFSET_X, BOARD_OFFSET_Y},
+	gravity::grav_to_rpf,
 	piece::{Piece, PieceType},
-	DAS_FRAMES,
 };
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
 	Down,
 	Up,
@@ -17,14 +18,14 @@ pub enum Direction {
 	Left,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Rotation {
 	CW,
 	CCW,
 	CCW2,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum RotIndex {
 	// default
 	Neutral,
@@ -36,6 +37,31 @@ pub enum RotIndex {
 	CW,
 }
 
+impl Direction {
+	pub fn action(self) -> Action {
+		match self {
+			Direction::Down => Action::Down,
+			Direction::Up => Action::Up,
+			Direction::Right => Action::Right,
+			Direction::Left => Action::Left,
+		}
+	}
+}
+
+impl Rotation {
+	// when more than one rotation button goes down on the same frame,
+	// A beats B beats C.
+	pub const PRIORITY: [Rotation; 3] = [Rotation::CCW, Rotation::CW, Rotation::CCW2];
+
+	pub fn action(self) -> Action {
+		match self {
+			Rotation::CW => Action::RotateCW,
+			Rotation::CCW => Action::RotateCCW,
+			Rotation::CCW2 => Action::RotateCCW2,
+		}
+	}
+}
+
 impl RotIndex {
 	fn rotate(self, rot: Rotation) -> Self {
 		match rot {
@@ -55,148 +81,155 @@ impl RotIndex {
 	}
 }
 
-#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct Movement {
-	pub dir: Option<Direction>,
-	pub rot: Option<Rotation>,
+	// left or right
+	pub horizontal: Option<Direction>,
+	// up or down. can be held at the same time as a horizontal direction.
+	pub vertical: Option<Direction>,
+	// every rotation button is its own thing, so pressing one while holding
+	// another still rotates.
+	pub rot: RotationButtons,
+	// the button went down since last frame. usually that's obvious from the
+	// last frame's movement, but not if it was let go and pressed again in between.
+	pub horizontal_pressed: bool,
+	pub vertical_pressed: bool,
+	pub rot_pressed: RotationButtons,
 }
 
-pub fn parse_movement(ctx: &mut Context) -> Movement {
-	let kb = &ctx.keyboard;
-
-	let dir: Option<Direction> = if kb.is_key_pressed(VirtualKeyCode::W) {
-		Some(Direction::Up)
-	} else if kb.is_key_pressed(VirtualKeyCode::A) {
-		Some(Direction::Left)
-	} else if kb.is_key_pressed(VirtualKeyCode::D) {
-		Some(Direction::Right)
-	} else if kb.is_key_pressed(VirtualKeyCode::S) {
-		Some(Direction::Down)
-	} else {
-		None
-	};
-
-	let rot: Option<Rotation> = if kb.is_key_pressed(VirtualKeyCode::H) {
-		Some(Rotation::CCW)
-	} else if kb.is_key_pressed(VirtualKeyCode::J) {
-		Some(Rotation::CW)
-	} else if kb.is_key_pressed(VirtualKeyCode::K) {
-		Some(Rotation::CCW2)
-	} else {
-		None
-	};
-
-	Movement { dir, rot }
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct RotationButtons {
+	pub ccw: bool,
+	pub cw: bool,
+	pub ccw2: bool,
 }
 
-pub fn is_movement_legal(piece: &Piece, board: &Board) -> bool {
-	let bx = piece.get_box().b;
-
-	// first row that contains any cells
-	let first_y = bx
-		.iter()
-		.position(|row| {
-			if row.iter().any(|s| matches!(s, Square::Filled(_))) {
-				return true;
-			}
-
-			false
-		})
-		.expect("unexpected empty piece? dying.") as i32;
-
-	let last_y = bx
-		.iter()
-		.rposition(|row| {
-			if row.iter().any(|s| matches!(s, Square::Filled(_))) {
-				return true;
-			}
-
-			false
-		})
-		.expect("unexpected empty piece? dying.") as i32;
-
-	// first x that contains any cells
-	let first_x = {
-		let mut min: i32 = 4;
-
-		for row in bx.iter() {
-			let mut x = 0;
-
-			for sqr in row {
-				if let Square::Filled(_) = sqr {
-					break;
-				}
-				x += 1;
-			}
-
-			if x < min {
-				min = x;
-			}
+impl RotationButtons {
+	pub fn get(&self, rot: Rotation) -> bool {
+		match rot {
+			Rotation::CW => self.cw,
+			Rotation::CCW => self.ccw,
+			Rotation::CCW2 => self.ccw2,
 		}
+	}
 
-		min
-	};
-
-	// largest x that contains any cells
-	let last_x = {
-		let mut max: i32 = 0;
-
-		for row in bx.iter() {
-			let mut m: i32 = 0;
-
-			for (x, sqr) in row.iter().enumerate() {
-				if let Square::Filled(_) = sqr {
-					m = x as i32;
-				}
-			}
-
-			if m > max {
-				max = m;
-			}
+	fn from_fn(f: impl Fn(Rotation) -> bool) -> Self {
+		RotationButtons {
+			ccw: f(Rotation::CCW),
+			cw: f(Rotation::CW),
+			ccw2: f(Rotation::CCW2),
 		}
+	}
 
-		max
-	};
-
-	let min_x = first_x + piece.x;
-	let max_x = last_x + piece.x;
-
-	// x and y stuff is inverted and i don't care to fix it
-	let min_y = piece.y - last_y;
-	let max_y = piece.y - first_y;
-
-	// going out of x bounds
-	if min_x < 0 || max_x >= BOARD_SIZE.0 as i32 {
-		return false;
+	// the highest priority button that's set
+	pub fn first(&self) -> Option<Rotation> {
+		Rotation::PRIORITY.into_iter().find(|r| self.get(*r))
 	}
+}
 
-	// going out of y bounds
-	if min_y <= 0 || max_y > BOARD_SIZE.1 as i32 {
-		return false;
+impl Movement {
+	// the rotation to do this frame, if any. a button has to be newly pressed,
+	// holding one never rotates twice.
+	pub fn new_rotation(&self, previous: &Movement) -> Option<Rotation> {
+		RotationButtons::from_fn(|r| {
+			self.rot_pressed.get(r) || (self.rot.get(r) && !previous.rot.get(r))
+		})
+		.first()
 	}
+}
 
-	let mut y = piece.y - 1;
+// what happens when two opposite directions are held at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter, Display)]
+pub enum Socd {
+	// whichever went down most recently
+	#[default]
+	#[strum(serialize = "last input wins")]
+	LastInput,
+	#[strum(serialize = "first input wins")]
+	FirstInput,
+	// they cancel out
+	Neutral,
+	// left beats right and up beats down, no matter the order.
+	#[strum(serialize = "left/up wins")]
+	Priority,
+}
 
-	// colliding with block?
-	for row in bx {
-		let mut x = piece.x;
+// what happens to a diagonal, like down-left on a stick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter, Display)]
+pub enum Diagonal {
+	// move and drop in the same frame
+	#[default]
+	Both,
+	#[strum(serialize = "horizontal wins")]
+	Horizontal,
+	#[strum(serialize = "vertical wins")]
+	Vertical,
+}
 
-		if let Some(b_row) = board.state.get(y as usize) {
-			for sqr in row {
-				// if this square is filled and the board is filled too
-				// we have a conflict, this move is not legal.
-				if let (Square::Filled(_), Some(Square::Filled(_))) = (sqr, b_row.get(x as usize)) {
-					return false;
-				}
+// picks between two opposite directions. each comes with when it was pressed
+// if it's held, and the first one is the one the priority rule likes.
+fn resolve_socd(
+	(first, first_at): (Direction, Option<u64>),
+	(second, second_at): (Direction, Option<u64>),
+	socd: Socd,
+) -> Option<Direction> {
+	match (first_at, second_at) {
+		(None, None) => None,
+		(Some(_), None) => Some(first),
+		(None, Some(_)) => Some(second),
+		(Some(a), Some(b)) => match socd {
+			Socd::LastInput if b > a => Some(second),
+			Socd::FirstInput if b < a => Some(second),
+			Socd::LastInput | Socd::FirstInput | Socd::Priority => Some(first),
+			Socd::Neutral => None,
+		},
+	}
+}
 
-				x += 1;
-			}
-		};
+// held_since is when an action was pressed, if it's held (or was tapped) this frame.
+// pressed is whether it went down since last frame.
+pub fn parse_movement(
+	held_since: impl Fn(Action) -> Option<u64>,
+	pressed: impl Fn(Action) -> bool,
+	socd: Socd,
+	diagonal: Diagonal,
+) -> Movement {
+	let held = |action| held_since(action).is_some();
+
+	let mut horizontal = resolve_socd(
+		(Direction::Left, held_since(Action::Left)),
+		(Direction::Right, held_since(Action::Right)),
+		socd,
+	);
+
+	let mut vertical = resolve_socd(
+		(Direction::Up, held_since(Action::Up)),
+		(Direction::Down, held_since(Action::Down)),
+		socd,
+	);
+
+	if horizontal.is_some() && vertical.is_some() {
+		match diagonal {
+			Diagonal::Both => (),
+			Diagonal::Horizontal => vertical = None,
+			Diagonal::Vertical => horizontal = None,
+		}
+	}
 
-		y -= 1;
+	Movement {
+		horizontal,
+		vertical,
+		rot: RotationButtons::from_fn(|r| held(r.action())),
+		horizontal_pressed: horizontal.is_some_and(|d| pressed(d.action())),
+		vertical_pressed: vertical.is_some_and(|d| pressed(d.action())),
+		rot_pressed: RotationButtons::from_fn(|r| pressed(r.action())),
 	}
+}
 
-	true
+pub fn is_movement_legal(piece: &Piece, board: &Board) -> bool {
+	piece.cells().into_iter().all(|(x, y)| {
+		board.contains(x, y) && matches!(board.state[y as usize][x as usize], Square::Empty)
+	})
 }
 
 fn apply_rot_kicks(orig_state: Piece, piece: &Piece, board: &Board) -> Piece {
@@ -229,8 +262,8 @@ fn apply_rot_kicks(orig_state: Piece, piece: &Piece, board: &Board) -> Piece {
 }
 
 // moves the piece down according to current gravity
-pub fn fall(piece: Piece, board: &Board, level: i32, grav_frames: i32) -> Option<Piece> {
-	let (rows, frames) = grav_to_rpf(get_gravity(level));
+pub fn fall(piece: Piece, board: &Board, gravity: i32, grav_frames: i32) -> Option<Piece> {
+	let (rows, frames) = grav_to_rpf(gravity);
 
 	let mut next_state = piece;
 
@@ -264,54 +297,180 @@ pub fn fall(piece: Piece, board: &Board, level: i32, grav_frames: i32) -> Option
 	Some(next_state)
 }
 
+// straight down as far as the piece goes, like TGM's up. it doesn't lock.
+pub fn sonic_drop(piece: Piece, board: &Board) -> Piece {
+	let mut dropped = piece;
+
+	while is_movement_legal(
+		&Piece {
+			y: dropped.y - 1,
+			..dropped
+		},
+		board,
+	) {
+		dropped.y -= 1;
+	}
+
+	dropped
+}
+
+// delayed auto shift. a held direction moves once, then after `das` more frames
+// starts repeating every `arr` frames. an arr of 0 goes straight to the wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoShift {
+	pub das: i32,
+	pub arr: i32,
+}
+
+// das_frames is how long the current horizontal direction has been held,
+// counting this frame. repeated holds in the same direction charge it,
+// anything else resets it.
+pub fn next_das_frames(das_frames: i32, previous_movement: &Movement, movement: &Movement) -> i32 {
+	match movement.horizontal {
+		Some(_)
+			if previous_movement.horizontal == movement.horizontal
+				&& !movement.horizontal_pressed =>
+		{
+			das_frames + 1
+		}
+		Some(_) => 1,
+		None => 0,
+	}
+}
+
+// how many cells a horizontal direction that's been held this long moves this frame.
+// an ARR of 0 goes as far as the board is wide.
+pub fn auto_shift_steps(das_frames: i32, shift: AutoShift, width: i32) -> i32 {
+	match das_frames {
+		1 => 1,
+		n if n > shift.das => {
+			if shift.arr == 0 {
+				width
+			} else if (n - shift.das - 1) % shift.arr == 0 {
+				1
+			} else {
+				0
+			}
+		}
+		_ => 0,
+	}
+}
+
+// the last thing that moved the piece, so a twist into place can be told
+// apart from sliding in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LastMove {
+	Shift,
+	Rotate,
+	// rotated, but only after being knocked sideways by a kick
+	Kick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Twist {
+	None,
+	Mini,
+	Full,
+}
+
+// a T that rotated into place with three of the four squares diagonal to its
+// middle filled is a T-spin. it's a mini unless both squares on the side it
+// points at are filled. walls and the floor count as filled.
+pub fn find_twist(piece: Piece, last_move: LastMove, board: &Board) -> Twist {
+	if piece.p_type != PieceType::T || last_move == LastMove::Shift {
+		return Twist::None;
+	}
+
+	// where the middle square is in the box, and which way the T points.
+	// the box's first row is the top one.
+	let (row, front) = match piece.rot_idx {
+		RotIndex::Neutral => (1, (0, -1)),
+		RotIndex::CW => (1, (-1, 0)),
+		RotIndex::U => (2, (0, 1)),
+		RotIndex::CCW => (1, (1, 0)),
+	};
+
+	let (cx, cy) = (piece.x + 1, piece.y - 1 - row);
+	let scale = if piece.big { 2 } else { 1 };
+
+	let filled = |(dx, dy): (i32, i32)| {
+		let (x, y) = ((cx + dx) * scale, (cy + dy) * scale);
+
+		!board.contains(x, y) || matches!(board.state[y as usize][x as usize], Square::Filled(_))
+	};
+
+	let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+	if corners.iter().filter(|c| filled(**c)).count() < 3 {
+		return Twist::None;
+	}
+
+	let front_filled = corners
+		.iter()
+		.filter(|(dx, dy)| (front.0 != 0 && *dx == front.0) || (front.1 != 0 && *dy == front.1))
+		.all(|c| filled(*c));
+
+	if front_filled {
+		Twist::Full
+	} else {
+		Twist::Mini
+	}
+}
+
+// moves the piece, and says what moved it last, if anything did.
 pub fn apply_movement(
 	previous_movement: &Movement,
 	movement: &Movement,
 	das_frames: i32,
+	shift: AutoShift,
 	piece: Piece,
 	board: &Board,
-) -> Piece {
-	let Movement { mut dir, mut rot } = movement;
-
+) -> (Piece, Option<LastMove>) {
 	// don't allow repeated rotations, ever
-	if previous_movement.rot == rot {
-		rot = None;
-	}
+	let rot = movement.new_rotation(previous_movement);
 
-	// only allow repeated dir moves if post-DAS
+	// horizontal moves only repeat once DAS is charged.
 	// repeated down is always legal though
-	if previous_movement.dir == Some(Direction::Down) && movement.dir == Some(Direction::Down)
-	// force das to 20hz instead of 60hz
-		|| (das_frames >= DAS_FRAMES && (das_frames - DAS_FRAMES % 3 == 0))
-	{
-	} else if das_frames < DAS_FRAMES && previous_movement.dir == dir {
-		dir = None;
-	}
+	let steps = match movement.horizontal {
+		Some(_) => auto_shift_steps(das_frames, shift, board.width() as i32),
+		None => 0,
+	};
 
 	let mut next_state = piece;
+	let mut last_move = None;
 
 	// if rotating, apply rotate
 	if let Some(r) = rot {
 		next_state.rot_idx = piece.rot_idx.rotate(r.to_owned());
 	}
 
-	// if moving, apply movement
-	if let Some(d) = dir {
+	// if moving, apply movement. a diagonal tries each direction on its own,
+	// so a shift into a wall doesn't stop the drop.
+	let horizontal = movement
+		.horizontal
+		.into_iter()
+		.flat_map(|d| (0..steps).map(move |_| d));
+
+	for d in horizontal.chain(movement.vertical) {
+		let mut moved = next_state;
+
 		match d {
-			Direction::Down => next_state.y -= 1,
-			Direction::Right => next_state.x += 1,
-			Direction::Left => next_state.x -= 1,
+			Direction::Down => moved.y -= 1,
+			Direction::Right => moved.x += 1,
+			Direction::Left => moved.x -= 1,
 			Direction::Up => (),
 		}
-	}
 
-	if !is_movement_legal(&next_state, board) {
-		next_state.x = piece.x;
-		next_state.y = piece.y;
+		if is_movement_legal(&moved, board) {
+			next_state = moved;
+			last_move = Some(LastMove::Shift);
+		}
 	}
 
 	// if rotating, apply rotate kicks
 	if rot.is_some() {
+		let unkicked = next_state;
+
 		// might revert the rotation, might not. tries to kick out of illegal scenarios.
 		next_state = apply_rot_kicks(piece, &next_state, board);
 
@@ -319,7 +478,151 @@ pub fn apply_movement(
 			next_state.x = piece.x;
 			next_state.y = piece.y;
 		}
+
+		// a shift on the same frame still counts as a twist, since the
+		// rotation gets the last word here
+		if next_state.rot_idx != piece.rot_idx {
+			last_move = Some(if next_state.x == unkicked.x {
+				LastMove::Rotate
+			} else {
+				LastMove::Kick
+			});
+		}
 	}
 
-	next_state
+	(next_state, last_move)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{rules::GameSettings, GameState, State, WaitingState, ARR_FRAMES, DAS_FRAMES};
+
+	const DEFAULT_SHIFT: AutoShift = AutoShift {
+		das: DAS_FRAMES,
+		arr: ARR_FRAMES,
+	};
+
+	fn held_right(pressed: bool) -> Movement {
+		Movement {
+			horizontal: Some(Direction::Right),
+			horizontal_pressed: pressed,
+			..Default::default()
+		}
+	}
+
+	// steps on each frame from 1 to das + 2 * arr + 1
+	fn steps(shift: AutoShift, width: i32) -> Vec<i32> {
+		(1..=shift.das + 2 * shift.arr + 1)
+			.map(|frames| auto_shift_steps(frames, shift, width))
+			.collect()
+	}
+
+	#[test]
+	fn default_das_and_arr() {
+		assert_eq!(DEFAULT_SHIFT, AutoShift { das: 16, arr: 3 });
+
+		// one step on the press, 15 frames of nothing, then a step every 3 frames
+		let expected = vec![
+			1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+			1, 0, 0, 1, 0, 0, 1,
+		];
+
+		assert_eq!(steps(DEFAULT_SHIFT, 10), expected);
+	}
+
+	#[test]
+	fn zero_arr_goes_to_the_wall() {
+		let shift = AutoShift { das: 16, arr: 0 };
+
+		let mut expected = vec![1];
+		expected.extend([0; 15]);
+		expected.push(10);
+
+		assert_eq!(steps(shift, 10), expected);
+		assert_eq!(auto_shift_steps(40, shift, 4), 4);
+	}
+
+	#[test]
+	fn das_frames_count_holds() {
+		let none = Movement::default();
+
+		assert_eq!(next_das_frames(0, &none, &held_right(true)), 1);
+		assert_eq!(next_das_frames(1, &held_right(true), &held_right(false)), 2);
+		// let go and pressed again between frames
+		assert_eq!(next_das_frames(9, &held_right(false), &held_right(true)), 1);
+		assert_eq!(next_das_frames(9, &held_right(false), &none), 0);
+	}
+
+	// holds frames of the direction until the game spawns a piece, then
+	// returns the new piece's das_frames and how many frames that took.
+	fn hold_until_active(game: &mut GameState) -> (i32, i32) {
+		let mut frames = 0;
+
+		while matches!(game.state, State::Waiting(_)) {
+			game.tick(held_right(false));
+			frames += 1;
+		}
+
+		match game.state {
+			State::Active(st) => (st.das_frames, frames),
+			_ => panic!("no piece spawned"),
+		}
+	}
+
+	#[test]
+	fn sonic_drop_lands_without_locking() {
+		// slow enough that the piece starts up top
+		let settings = GameSettings {
+			start_level: 0,
+			..Default::default()
+		};
+		let mut game = GameState::new(settings, 1);
+
+		while matches!(game.state, State::Waiting(_)) {
+			game.tick(Movement::default());
+		}
+
+		game.tick(Movement {
+			vertical: Some(Direction::Up),
+			vertical_pressed: true,
+			..Default::default()
+		});
+
+		let State::Active(st) = game.state else {
+			panic!("the piece locked");
+		};
+
+		assert!(st.sonic_rows > 0);
+		assert_eq!(st.piece.cells().iter().map(|(_, y)| *y).min(), Some(0));
+	}
+
+	#[test]
+	fn das_charges_through_are() {
+		let mut game = GameState::new(GameSettings::default(), 1);
+
+		game.tick(held_right(true));
+		let (das_frames, frames) = hold_until_active(&mut game);
+
+		assert_eq!(das_frames, frames + 1);
+		assert!(das_frames > DAS_FRAMES);
+	}
+
+	#[test]
+	fn das_charges_through_line_clears() {
+		let mut game = GameState::new(GameSettings::default(), 1);
+		let timings = game.timings();
+
+		game.movement = held_right(false);
+		game.state = State::Waiting(WaitingState {
+			waiting_frames: 0,
+			das_frames: 5,
+			did_clear_line: true,
+		});
+
+		let (das_frames, frames) = hold_until_active(&mut game);
+
+		assert_eq!(frames, timings.line_clear + timings.line_are + 1);
+		assert_eq!(das_frames, 5 + frames);
+	}
 }
@@ -1,17 +1,13 @@
 use ggez::graphics::Color;
-use rand::seq::SliceRandom;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
-const INITIAL_SPAWN_X: i32 = 3;
-const INITIAL_SPAWN_Y: i32 = 21;
-
 use crate::{
 	board::{PieceBox, Square},
 	movement::RotIndex,
 };
 
-#[derive(Debug, EnumIter, Clone, PartialEq, Eq, Copy)]
+#[derive(Debug, EnumIter, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub enum PieceType {
 	Z,
 	S,
@@ -22,7 +18,7 @@ pub enum PieceType {
 	I,
 }
 
-const VEC_PIECES: [PieceType; 7] = [
+pub const VEC_PIECES: [PieceType; 7] = [
 	PieceType::Z,
 	PieceType::S,
 	PieceType::T,
@@ -33,15 +29,20 @@ const VEC_PIECES: [PieceType; 7] = [
 ];
 
 impl PieceType {
-	pub fn random<R: Rng>(rng: &mut R) -> Self {
-		let piece = VEC_PIECES
-			.choose(rng)
-			.expect("vec_pieces was empty, no pieces are defined?");
-
-		*piece
+	pub fn from_letter(c: char) -> Option<Self> {
+		match c.to_ascii_uppercase() {
+			'Z' => Some(PieceType::Z),
+			'S' => Some(PieceType::S),
+			'T' => Some(PieceType::T),
+			'L' => Some(PieceType::L),
+			'J' => Some(PieceType::J),
+			'O' => Some(PieceType::O),
+			'I' => Some(PieceType::I),
+			_ => None,
+		}
 	}
 
-	fn get_color(self) -> Color {
+	pub fn get_color(self) -> Color {
 		match self {
 			PieceType::Z => Color::GREEN,
 			PieceType::S => Color::MAGENTA,
@@ -63,25 +64,57 @@ impl PieceType {
 		}
 	}
 
+	// at the bottom left. Board::spawn puts it where it goes.
 	pub fn to_piece(self) -> Piece {
 		Piece {
 			p_type: self,
 			rot_idx: RotIndex::Neutral,
-			x: INITIAL_SPAWN_X,
-			y: INITIAL_SPAWN_Y,
+			x: 0,
+			y: 0,
+			big: false,
 		}
 	}
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Piece {
 	pub p_type: PieceType,
 	pub rot_idx: RotIndex,
 	pub x: i32,
 	pub y: i32,
+	// every square is 2x2, and x and y count in those
+	pub big: bool,
 }
 
 impl Piece {
+	pub fn to_big(self) -> Piece {
+		Piece { big: true, ..self }
+	}
+
+	// the board squares the piece covers, as (x, y).
+	pub fn cells(self) -> Vec<(i32, i32)> {
+		let scale = if self.big { 2 } else { 1 };
+		let mut cells = vec![];
+
+		for (r, row) in self.get_box().b.iter().enumerate() {
+			for (c, sqr) in row.iter().enumerate() {
+				if !matches!(sqr, Square::Filled(_)) {
+					continue;
+				}
+
+				let (x, y) = (self.x + c as i32, self.y - 1 - r as i32);
+
+				for dy in 0..scale {
+					for dx in 0..scale {
+						cells.push((x * scale + dx, y * scale + dy));
+					}
+				}
+			}
+		}
+
+		cells
+	}
+
 	pub fn get_box(self) -> PieceBox {
 		let c = self.p_type.get_color();
 
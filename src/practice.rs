@@ -0,0 +1,89 @@
+// practice mode: a fixed board, queue and level you can retry forever.
+
+use crate::{board::Board, fumen::FumenPage, piece::PieceType, rules::RollKind};
+
+pub const DEFAULT_PRACTICE_LEVEL: i32 = 500;
+
+#[derive(Debug, Clone)]
+pub struct PracticeConfig {
+	pub board: Board,
+	// dealt in order, and dealt again from the start when it runs out.
+	// if it's empty the randomiser picks pieces like normal.
+	pub queue: Vec<PieceType>,
+	// the level never changes in practice, so neither does gravity
+	pub level: i32,
+	// straight into the credit roll, for getting used to it
+	pub roll: RollKind,
+}
+
+impl Default for PracticeConfig {
+	fn default() -> Self {
+		PracticeConfig {
+			board: Board::default(),
+			queue: vec![],
+			level: DEFAULT_PRACTICE_LEVEL,
+			roll: RollKind::Off,
+		}
+	}
+}
+
+impl PracticeConfig {
+	// the page's piece comes first, then its queue.
+	pub fn from_fumen(page: FumenPage) -> Self {
+		let mut queue: Vec<PieceType> = page.piece.map(|p| p.p_type).into_iter().collect();
+		queue.extend(page.queue);
+
+		PracticeConfig {
+			board: page.board,
+			queue,
+			..Default::default()
+		}
+	}
+}
+
+pub fn parse_queue(queue: &str) -> Result<Vec<PieceType>, String> {
+	queue
+		.chars()
+		.map(|c| PieceType::from_letter(c).ok_or(format!("'{}' is not a piece", c)))
+		.collect()
+}
+
+pub fn parse_roll(roll: &str) -> Result<RollKind, String> {
+	use strum::IntoEnumIterator;
+
+	RollKind::iter()
+		.find(|r| r.to_string() == roll)
+		.ok_or(format!("'{}' is not a roll, try fading or invisible", roll))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::{movement::Movement, GameState};
+
+	#[test]
+	fn retry_stays_in_practice() {
+		let config = PracticeConfig {
+			queue: parse_queue("TI").unwrap(),
+			level: 100,
+			..Default::default()
+		};
+
+		let mut game = GameState::practice(config);
+		game.savestate_path = PathBuf::from("mine.savestate");
+
+		for _ in 0..200 {
+			game.tick(Movement::default());
+		}
+
+		game.retry();
+
+		assert!(game.practice.is_some());
+		assert_eq!(game.level, 100);
+		assert_eq!(game.frames, 0);
+		assert_eq!(game.next_piece, PieceType::T);
+		assert_eq!(game.savestate_path, PathBuf::from("mine.savestate"));
+	}
+}
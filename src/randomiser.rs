@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::piece::{PieceType, VEC_PIECES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum RandomiserKind {
+	// 4 tries at avoiding the history
+	#[strum(serialize = "TGM1")]
+	Tgm1,
+	// 6 tries, and a different starting history
+	#[strum(serialize = "TAP")]
+	Tap,
+	// anything goes, every time
+	Memoryless,
+}
+
+impl RandomiserKind {
+	fn tries(self) -> i32 {
+		match self {
+			RandomiserKind::Tgm1 => 4,
+			RandomiserKind::Tap => 6,
+			RandomiserKind::Memoryless => 1,
+		}
+	}
+
+	fn starting_history(self) -> [PieceType; 4] {
+		match self {
+			RandomiserKind::Tap => [PieceType::Z, PieceType::S, PieceType::S, PieceType::Z],
+			_ => [PieceType::Z; 4],
+		}
+	}
+}
+
+// TGM's randomiser: a 4 piece history, rerolled a few times
+// on top of a plain old LCG. we keep our own LCG instead of using rand
+// so the whole thing is Copy, and can be saved and restored.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Randomiser {
+	pub kind: RandomiserKind,
+	// what this game started from, so it can be played again
+	pub seed: u32,
+	state: u32,
+	history: [PieceType; 4],
+	// nothing's been dealt yet this game
+	first_draw: bool,
+}
+
+impl Randomiser {
+	pub fn new(kind: RandomiserKind, seed: u32) -> Self {
+		Randomiser {
+			kind,
+			seed,
+			state: seed,
+			history: kind.starting_history(),
+			first_draw: true,
+		}
+	}
+
+	fn next(&mut self) -> u32 {
+		self.state = self.state.wrapping_mul(0x41c64e6d).wrapping_add(12345);
+
+		(self.state >> 10) & 0x7fff
+	}
+
+	// remember a piece that was dealt, so we try not to deal it again soon.
+	pub fn push_history(&mut self, piece: PieceType) {
+		self.history.rotate_left(1);
+		self.history[3] = piece;
+	}
+
+	pub fn draw(&mut self) -> PieceType {
+		let mut new_piece: Option<PieceType> = None;
+
+		// try X times to generate a piece we haven't seen before.
+		for _ in 0..self.kind.tries() {
+			let piece = VEC_PIECES[self.next() as usize % VEC_PIECES.len()];
+			new_piece = Some(piece);
+
+			if !self.history.contains(&piece) {
+				break;
+			}
+		}
+
+		let new_piece = new_piece.expect("Every randomiser has at least one try.");
+
+		// if this is the first piece we drew this game
+		// dont let it be S/Z/O.
+		// We do this recursively instead of a loop, that's probably fine. Right?
+		if self.kind != RandomiserKind::Memoryless
+			&& self.first_draw
+			&& (new_piece == PieceType::S || new_piece == PieceType::Z || new_piece == PieceType::O)
+		{
+			return self.draw();
+		}
+
+		self.first_draw = false;
+
+		new_piece
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn first_piece_is_never_s_z_or_o() {
+		for kind in [RandomiserKind::Tgm1, RandomiserKind::Tap] {
+			for seed in 0..500 {
+				let first = Randomiser::new(kind, seed).draw();
+
+				assert!(
+					![PieceType::S, PieceType::Z, PieceType::O].contains(&first),
+					"{:?} dealt {:?} first with seed {}",
+					kind,
+					first,
+					seed
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn later_pieces_can_be_anything() {
+		let mut randomiser = Randomiser::new(RandomiserKind::Tgm1, 1);
+		let mut dealt = vec![];
+
+		for _ in 0..500 {
+			let piece = randomiser.draw();
+
+			randomiser.push_history(piece);
+
+			if !dealt.contains(&piece) {
+				dealt.push(piece);
+			}
+		}
+
+		assert_eq!(dealt.len(), VEC_PIECES.len());
+	}
+}
@@ -0,0 +1,183 @@
+// replays: the seed and every frame of input. the game is deterministic
+// given those, so that's all it takes to play a game back.
+
+use std::{fmt::Display, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{board::BoardSize, movement::Movement, rules::GameSettings};
+
+pub const REPLAY_VERSION: u32 = 17;
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+	pub settings: GameSettings,
+	pub seed: u32,
+	pub inputs: Vec<Movement>,
+}
+
+// inputs are stored as runs, since most frames are the same as the last.
+#[derive(Debug, Serialize, Deserialize)]
+struct ReplayFile {
+	version: u32,
+	settings: GameSettings,
+	seed: u32,
+	inputs: Vec<(Movement, u32)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplayVersion {
+	version: u32,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+	Io(std::io::Error),
+	Format(serde_json::Error),
+	WrongVersion(u32),
+	UnplayableBoard(BoardSize),
+}
+
+impl Display for ReplayError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ReplayError::Io(err) => write!(f, "couldn't access replay: {}", err),
+			ReplayError::Format(err) => write!(f, "replay is broken: {}", err),
+			ReplayError::WrongVersion(v) => write!(
+				f,
+				"replay is version {}, but this game only reads version {}",
+				v, REPLAY_VERSION
+			),
+			ReplayError::UnplayableBoard(size) => write!(
+				f,
+				"replay's board is {}x{} with {} hidden rows, which can't be played",
+				size.width, size.visible, size.hidden
+			),
+		}
+	}
+}
+
+impl From<std::io::Error> for ReplayError {
+	fn from(err: std::io::Error) -> Self {
+		ReplayError::Io(err)
+	}
+}
+
+impl From<serde_json::Error> for ReplayError {
+	fn from(err: serde_json::Error) -> Self {
+		ReplayError::Format(err)
+	}
+}
+
+pub fn save(path: &Path, replay: &Replay) -> Result<(), ReplayError> {
+	let mut inputs: Vec<(Movement, u32)> = vec![];
+
+	for movement in replay.inputs.iter() {
+		match inputs.last_mut() {
+			Some((last, count)) if last == movement => *count += 1,
+			_ => inputs.push((*movement, 1)),
+		}
+	}
+
+	let data = serde_json::to_string(&ReplayFile {
+		version: REPLAY_VERSION,
+		settings: replay.settings.clone(),
+		seed: replay.seed,
+		inputs,
+	})?;
+
+	fs::write(path, data)?;
+
+	Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+	let data = fs::read_to_string(path)?;
+
+	let ReplayVersion { version } = serde_json::from_str(&data)?;
+
+	if version != REPLAY_VERSION {
+		return Err(ReplayError::WrongVersion(version));
+	}
+
+	let file: ReplayFile = serde_json::from_str(&data)?;
+
+	if !file.settings.board.is_playable() {
+		return Err(ReplayError::UnplayableBoard(file.settings.board));
+	}
+
+	Ok(Replay {
+		settings: file.settings,
+		seed: file.seed,
+		inputs: file
+			.inputs
+			.into_iter()
+			.flat_map(|(movement, count)| (0..count).map(move |_| movement))
+			.collect(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::board::DEFAULT_BOARD_SIZE;
+
+	fn replay_with(board: BoardSize) -> Replay {
+		Replay {
+			settings: GameSettings {
+				board,
+				..Default::default()
+			},
+			seed: 1,
+			inputs: vec![Movement::default(); 3],
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let path = std::env::temp_dir().join("rgm-test-round-trip.replay");
+
+		save(&path, &replay_with(DEFAULT_BOARD_SIZE)).unwrap();
+		let replay = load(&path).unwrap();
+
+		assert_eq!(replay.settings, replay_with(DEFAULT_BOARD_SIZE).settings);
+		assert_eq!(replay.inputs.len(), 3);
+	}
+
+	#[test]
+	fn buffer_zones_load() {
+		let path = std::env::temp_dir().join("rgm-test-buffer-zone.replay");
+		let board = BoardSize {
+			hidden: 20,
+			..DEFAULT_BOARD_SIZE
+		};
+
+		save(&path, &replay_with(board)).unwrap();
+
+		assert_eq!(load(&path).unwrap().settings.board, board);
+	}
+
+	#[test]
+	fn unplayable_boards_dont_load() {
+		let path = std::env::temp_dir().join("rgm-test-unplayable.replay");
+
+		for board in [
+			BoardSize {
+				hidden: 0,
+				..DEFAULT_BOARD_SIZE
+			},
+			BoardSize {
+				width: 3,
+				..DEFAULT_BOARD_SIZE
+			},
+			BoardSize {
+				visible: 0,
+				..DEFAULT_BOARD_SIZE
+			},
+		] {
+			save(&path, &replay_with(board)).unwrap();
+
+			assert!(matches!(load(&path), Err(ReplayError::UnplayableBoard(_))));
+		}
+	}
+}
@@ -0,0 +1,110 @@
+// the results screen, shown once the game over animation is done.
+
+use ggez::{
+	glam::Vec2,
+	graphics::{self, Color, DrawParam, Text},
+};
+
+use crate::{board::Board, grading::secret_grade, Ending, GameState, State, FPS};
+
+// the board goes grey one row every 10 frames, then we wait a little.
+pub fn results_after_mono_frames(board: &Board) -> i32 {
+	board.height() as i32 * 10 + 30
+}
+
+// mm:ss:cc, like the arcade timer.
+pub fn format_frames(frames: u32) -> String {
+	let centis = frames * 100 / FPS;
+
+	format!(
+		"{:02}:{:02}:{:02}",
+		centis / 6000,
+		(centis / 100) % 60,
+		centis % 100
+	)
+}
+
+pub fn draw_results(canvas: &mut graphics::Canvas, game: &GameState) {
+	let record = &game.player_record;
+
+	canvas.draw(
+		&graphics::Quad,
+		DrawParam::default()
+			.color(Color::new(0.0, 0.0, 0.0, 0.85))
+			.scale([800., 600.]),
+	);
+
+	let mut sections = String::new();
+	let mut last = 0;
+	let judgements = game.grader.system().judgements();
+
+	let section_count = game.settings.rules.section_count();
+
+	for (i, split) in record.sections.iter().enumerate().take(section_count) {
+		if let Some(split) = split {
+			sections.push_str(&format!(
+				"{:03}-{:03}  {}  ({})  {}\n",
+				i * 100,
+				i * 100 + 99,
+				format_frames(split - last),
+				format_frames(*split),
+				judgements
+					.and_then(|j| j.get(i))
+					.map_or(String::new(), |j| j.to_string()),
+			));
+
+			last = *split;
+		}
+	}
+
+	let [singles, doubles, triples, tetrises] = record.lines;
+
+	let secret = match secret_grade(&game.board) {
+		Some(grade) => format!("secret grade {}\n", grade),
+		None => String::new(),
+	};
+
+	let ending = match &game.state {
+		State::GameOver(st) => match st.ending {
+			Ending::ToppedOut => String::new(),
+			Ending::Torikan(level) => {
+				let limit = game.settings.torikans.iter().find(|t| t.level == level);
+
+				format!(
+					"too slow for the level {} torikan ({})\n",
+					level,
+					limit.map_or(String::new(), |t| format_frames(t.frames))
+				)
+			}
+			Ending::Cleared => "cleared\n".to_string(),
+			Ending::RollCleared(roll) => format!("survived the {} roll\n", roll),
+		},
+		_ => String::new(),
+	};
+
+	let text = format!(
+		"RESULTS\n{}\n\
+		grade {}\n\
+		{}\
+		score {}\n\
+		level {}\n\
+		time  {}\n\n\
+		sections\n{}\n\
+		singles {}  doubles {}  triples {}  tetrises {}\n\n\
+		seed {}",
+		ending,
+		record.grade,
+		secret,
+		record.score,
+		game.level,
+		format_frames(game.frames),
+		sections,
+		singles,
+		doubles,
+		triples,
+		tetrises,
+		game.randomiser.seed,
+	);
+
+	canvas.draw(&Text::new(text), Vec2::new(100., 60.));
+}
@@ -0,0 +1,461 @@
+// what sort of game is being played. picked on the mode select screen,
+// and kept around so retries and replays play the same thing.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+use crate::{
+	board::{BoardSize, DEFAULT_BOARD_SIZE},
+	grading::GradingKind,
+	gravity::get_gravity,
+	movement::AutoShift,
+	randomiser::RandomiserKind,
+	scoring::{ScoringKind, MAX_SECTION_COUNT, SECTION_COUNT},
+	ARR_FRAMES, DAS_FRAMES, FPS,
+};
+
+pub const TWENTY_G: i32 = 5120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum RuleSet {
+	// TGM1's gravity curve
+	Classic,
+	// 20G from the very first piece
+	#[strum(serialize = "20G")]
+	TwentyG,
+	// TAP's Death: 20G, and everything else gets faster every section
+	Death,
+	// TGM3's Shirase: Death, but faster, with garbage, and big and grey from 1000
+	Shirase,
+}
+
+// how long things take, in frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Timings {
+	pub are: i32,
+	// after a piece clears lines, the lines take line_clear to go away,
+	// then there's line_are before the next piece
+	pub line_clear: i32,
+	pub line_are: i32,
+	pub lock_delay: i32,
+	// None leaves it up to the player
+	pub das: Option<i32>,
+}
+
+// TGM1's, which never change.
+const CLASSIC_TIMINGS: Timings = Timings {
+	are: 30,
+	line_clear: 41,
+	line_are: 30,
+	lock_delay: 30,
+	das: None,
+};
+
+const fn death_timings(
+	are: i32,
+	line_are: i32,
+	das: i32,
+	lock_delay: i32,
+	line_clear: i32,
+) -> Timings {
+	Timings {
+		are,
+		line_clear,
+		line_are,
+		lock_delay,
+		das: Some(das),
+	}
+}
+
+// TAP Death's, a section at a time. 500 onwards stays on the last one.
+// ARE, line ARE, DAS, lock delay, line clear.
+const DEATH_TIMINGS: [Timings; 6] = [
+	death_timings(18, 14, 12, 30, 12),
+	death_timings(14, 8, 12, 26, 6),
+	death_timings(14, 8, 11, 22, 6),
+	death_timings(8, 8, 10, 18, 6),
+	death_timings(7, 7, 8, 15, 5),
+	death_timings(6, 6, 8, 15, 4),
+];
+
+// TGM3 Shirase's, like Death's.
+const SHIRASE_TIMINGS: [Timings; 8] = [
+	death_timings(12, 8, 10, 18, 6),
+	death_timings(12, 7, 8, 18, 5),
+	death_timings(12, 6, 8, 17, 4),
+	death_timings(6, 6, 8, 15, 4),
+	death_timings(5, 5, 6, 13, 3),
+	death_timings(4, 4, 6, 12, 3),
+	death_timings(4, 4, 6, 10, 3),
+	death_timings(4, 4, 6, 8, 3),
+];
+
+// how many pieces in a row without a clear before Shirase's garbage
+// rises, for 500-599 through 900-999.
+const SHIRASE_GARBAGE_QUOTAS: [i32; 5] = [20, 18, 10, 9, 8];
+
+impl RuleSet {
+	pub fn gravity(self, level: i32) -> i32 {
+		match self {
+			RuleSet::Classic => get_gravity(level),
+			RuleSet::TwentyG | RuleSet::Death | RuleSet::Shirase => TWENTY_G,
+		}
+	}
+
+	pub fn timings(self, level: i32) -> Timings {
+		match self {
+			RuleSet::Classic | RuleSet::TwentyG => CLASSIC_TIMINGS,
+			RuleSet::Death => {
+				DEATH_TIMINGS[(level / 100).clamp(0, DEATH_TIMINGS.len() as i32 - 1) as usize]
+			}
+			RuleSet::Shirase => {
+				SHIRASE_TIMINGS[(level / 100).clamp(0, SHIRASE_TIMINGS.len() as i32 - 1) as usize]
+			}
+		}
+	}
+
+	// pieces without a clear before the bottom row gets copied up, if the
+	// rules have garbage at this level.
+	pub fn garbage_quota(self, level: i32) -> Option<i32> {
+		match self {
+			RuleSet::Shirase if (500..1000).contains(&level) => {
+				Some(SHIRASE_GARBAGE_QUOTAS[(level / 100 - 5) as usize])
+			}
+			_ => None,
+		}
+	}
+
+	// the level that clears the game. Shirase keeps going to 1300.
+	pub fn max_level(self) -> i32 {
+		match self {
+			RuleSet::Shirase => 1300,
+			_ => 999,
+		}
+	}
+
+	// sections of 100 levels, the last one finished by the max level
+	pub fn section_count(self) -> usize {
+		match self {
+			RuleSet::Shirase => MAX_SECTION_COUNT,
+			_ => SECTION_COUNT,
+		}
+	}
+
+	// every block goes grey
+	pub fn is_monochrome(self, level: i32) -> bool {
+		self == RuleSet::Shirase && level >= 1000
+	}
+
+	// pieces are big from here on, whether or not big mode is on
+	pub fn is_big(self, level: i32) -> bool {
+		self == RuleSet::Shirase && level >= 1000
+	}
+}
+
+// a time limit on reaching a level. get there late and the game's over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Torikan {
+	pub level: i32,
+	pub frames: u32,
+}
+
+const fn minutes(minutes: u32, seconds: u32) -> u32 {
+	(minutes * 60 + seconds) * FPS
+}
+
+// Death's torikan, which is also what it takes to be an M there.
+pub const DEATH_TORIKAN: Torikan = Torikan {
+	level: 500,
+	frames: minutes(3, 25),
+};
+
+// the arcade's torikans, for picking on the mode select screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+pub enum TorikanPreset {
+	Off,
+	#[strum(serialize = "TAP Death")]
+	TapDeath,
+	#[strum(serialize = "TGM3 Master")]
+	Tgm3Master,
+	#[strum(serialize = "TGM3 Shirase")]
+	Tgm3Shirase,
+}
+
+impl TorikanPreset {
+	pub fn torikans(self) -> Vec<Torikan> {
+		let torikan = |level, frames| Torikan { level, frames };
+
+		match self {
+			TorikanPreset::Off => vec![],
+			TorikanPreset::TapDeath => vec![DEATH_TORIKAN],
+			TorikanPreset::Tgm3Master => vec![torikan(500, minutes(7, 0))],
+			TorikanPreset::Tgm3Shirase => {
+				vec![torikan(500, minutes(2, 28)), torikan(1000, minutes(4, 56))]
+			}
+		}
+	}
+
+	// None for a list that isn't one of ours.
+	pub fn find(torikans: &[Torikan]) -> Option<Self> {
+		use strum::IntoEnumIterator;
+
+		TorikanPreset::iter().find(|p| p.torikans() == torikans)
+	}
+}
+
+// the credit roll after 999. the stack gets cleared, and you survive
+// the credits with blocks that vanish once they lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum RollKind {
+	// no roll, the game just keeps going
+	#[strum(serialize = "off")]
+	Off,
+	// blocks fade out a few seconds after locking
+	#[strum(serialize = "fading")]
+	Fading,
+	// blocks disappear as soon as they lock
+	#[strum(serialize = "invisible")]
+	Invisible,
+}
+
+// TAP's credits
+pub const ROLL_FRAMES: u32 = 3238;
+const ROLL_FADE_FRAMES: i32 = 5 * FPS as i32;
+
+impl RollKind {
+	// how long a block stays visible once it locks. None is forever.
+	pub fn vanish_after(self) -> Option<i32> {
+		match self {
+			RollKind::Off => None,
+			RollKind::Fading => Some(ROLL_FADE_FRAMES),
+			RollKind::Invisible => Some(0),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSettings {
+	pub start_level: i32,
+	pub rules: RuleSet,
+	pub randomiser: RandomiserKind,
+	pub grading: GradingKind,
+	pub scoring: ScoringKind,
+	pub shift: AutoShift,
+	pub torikans: Vec<Torikan>,
+	// every piece is big
+	pub big: bool,
+	pub board: BoardSize,
+	pub roll: RollKind,
+}
+
+impl Default for GameSettings {
+	fn default() -> Self {
+		GameSettings {
+			// I play 20G, so this is where I like to start.
+			start_level: 500,
+			rules: RuleSet::Classic,
+			randomiser: RandomiserKind::Tgm1,
+			grading: GradingKind::Tgm1,
+			scoring: ScoringKind::Tgm1,
+			shift: AutoShift {
+				das: DAS_FRAMES,
+				arr: ARR_FRAMES,
+			},
+			torikans: vec![],
+			big: false,
+			board: DEFAULT_BOARD_SIZE,
+			roll: RollKind::Off,
+		}
+	}
+}
+
+// big squares are two columns wide, so big pieces need an even width,
+// and room for a big I piece to spawn lying flat.
+const MIN_BIG_WIDTH: usize = 8;
+
+// and a big piece's top row is two rows tall, above the frame.
+const MIN_BIG_HIDDEN: usize = 2;
+
+impl GameSettings {
+	// whether pieces are big at any point in the game
+	pub fn can_be_big(&self) -> bool {
+		self.big || self.rules == RuleSet::Shirase
+	}
+
+	// the board this mode plays on, given more room if big pieces
+	// wouldn't fit.
+	pub fn board_size(&self) -> BoardSize {
+		if !self.can_be_big() {
+			return self.board;
+		}
+
+		let width = self.board.width.max(MIN_BIG_WIDTH);
+		let hidden = self.board.hidden.max(MIN_BIG_HIDDEN);
+
+		BoardSize {
+			width: width + width % 2,
+			hidden: hidden + hidden % 2,
+			..self.board
+		}
+	}
+
+	// picking Death picks the rest of the mode too. it can all still be
+	// changed afterwards, and DAS/ARR are left alone.
+	pub fn with_death(self) -> Self {
+		GameSettings {
+			start_level: 0,
+			rules: RuleSet::Death,
+			randomiser: RandomiserKind::Tap,
+			grading: GradingKind::Death,
+			scoring: ScoringKind::Tap,
+			torikans: TorikanPreset::TapDeath.torikans(),
+			..self
+		}
+	}
+
+	// the same for Shirase, with its own grading, scoring and torikans.
+	pub fn with_shirase(self) -> Self {
+		GameSettings {
+			start_level: 0,
+			rules: RuleSet::Shirase,
+			randomiser: RandomiserKind::Tap,
+			grading: GradingKind::Shirase,
+			scoring: ScoringKind::Tgm3,
+			torikans: TorikanPreset::Tgm3Shirase.torikans(),
+			..self
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use strum::IntoEnumIterator;
+
+	use super::*;
+	use crate::{
+		board::{Board, HIDDEN_RANGE, VISIBLE_RANGE, WIDTH_RANGE},
+		movement::{is_movement_legal, Movement, RotIndex},
+		piece::{Piece, PieceType},
+		Ending, GameOverState, GameState, State,
+	};
+
+	// every board mode select can make
+	fn every_board_size() -> impl Iterator<Item = BoardSize> {
+		WIDTH_RANGE.flat_map(|width| {
+			VISIBLE_RANGE.flat_map(move |visible| {
+				HIDDEN_RANGE.map(move |hidden| BoardSize {
+					width,
+					visible,
+					hidden,
+				})
+			})
+		})
+	}
+
+	#[test]
+	fn big_pieces_fit_every_board() {
+		for board in every_board_size() {
+			for settings in [
+				GameSettings {
+					big: true,
+					..Default::default()
+				},
+				GameSettings::default().with_shirase(),
+			] {
+				let settings = GameSettings { board, ..settings };
+
+				let size = settings.board_size();
+				assert!(size.is_playable());
+				assert!(size.width >= board.width && size.width >= 8);
+				assert_eq!(size.width % 2, 0);
+				assert!(size.hidden >= 2);
+				assert_eq!(size.hidden % 2, 0);
+				assert_eq!(size.visible, board.visible);
+
+				let board = Board::new(size);
+
+				// every orientation, so IRS has room too
+				for p_type in PieceType::iter() {
+					for rot_idx in [RotIndex::Neutral, RotIndex::CW, RotIndex::CCW, RotIndex::U] {
+						let piece = board.spawn(Piece {
+							rot_idx,
+							..p_type.to_piece().to_big()
+						});
+						assert!(
+							is_movement_legal(&piece, &board),
+							"{:?} {:?} on {:?}",
+							p_type,
+							rot_idx,
+							size
+						);
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn small_pieces_fit_every_board() {
+		for size in every_board_size() {
+			let board = Board::new(size);
+
+			for p_type in PieceType::iter() {
+				for rot_idx in [RotIndex::Neutral, RotIndex::CW, RotIndex::CCW, RotIndex::U] {
+					let piece = board.spawn(Piece {
+						rot_idx,
+						..p_type.to_piece()
+					});
+					assert!(
+						is_movement_legal(&piece, &board),
+						"{:?} {:?} on {:?}",
+						p_type,
+						rot_idx,
+						size
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn shirase_goes_big_and_ends_at_1300() {
+		let mut game = GameState::new(GameSettings::default().with_shirase(), 1);
+
+		// 999 doesn't end it
+		game.level = 990;
+		game.add_levels(20);
+		game.tick(Movement::default());
+
+		assert_eq!(game.level, 1010);
+		assert!(!matches!(game.state, State::GameOver(_)));
+		assert!(game.settings.rules.is_monochrome(game.level));
+		assert!(game.iter_piece().big);
+
+		game.level = 1298;
+		game.add_levels(4);
+		game.tick(Movement::default());
+
+		assert_eq!(game.level, 1300);
+		assert!(game.player_record.sections[MAX_SECTION_COUNT - 1].is_some());
+		assert!(matches!(
+			game.state,
+			State::GameOver(GameOverState {
+				ending: Ending::Cleared,
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn small_pieces_keep_the_width() {
+		let settings = GameSettings {
+			board: BoardSize {
+				width: 5,
+				..DEFAULT_BOARD_SIZE
+			},
+			..Default::default()
+		};
+
+		assert_eq!(settings.board_size().width, 5);
+	}
+}
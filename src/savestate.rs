@@ -0,0 +1,137 @@
+// savestates: a snapshot of the whole game, written to a file so it can be
+// loaded back later, or by someone else.
+
+use std::{fmt::Display, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{board::BoardSize, rules::GameSettings, snapshot::Snapshot};
+
+// bump this whenever anything in a snapshot changes shape.
+pub const SAVESTATE_VERSION: u32 = 15;
+
+pub const DEFAULT_SAVESTATE_PATH: &str = "rgm.savestate";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	version: u32,
+	settings: GameSettings,
+	snapshot: Snapshot,
+}
+
+// just enough to read the version out of any savestate, old or new.
+#[derive(Debug, Deserialize)]
+struct SaveStateVersion {
+	version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveStateError {
+	Io(std::io::Error),
+	Format(serde_json::Error),
+	WrongVersion(u32),
+	UnplayableBoard(BoardSize),
+}
+
+impl Display for SaveStateError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SaveStateError::Io(err) => write!(f, "couldn't access savestate: {}", err),
+			SaveStateError::Format(err) => write!(f, "savestate is broken: {}", err),
+			SaveStateError::WrongVersion(v) => write!(
+				f,
+				"savestate is version {}, but this game only reads version {}",
+				v, SAVESTATE_VERSION
+			),
+			SaveStateError::UnplayableBoard(size) => write!(
+				f,
+				"savestate's board is {}x{} with {} hidden rows, which can't be played",
+				size.width, size.visible, size.hidden
+			),
+		}
+	}
+}
+
+impl From<std::io::Error> for SaveStateError {
+	fn from(err: std::io::Error) -> Self {
+		SaveStateError::Io(err)
+	}
+}
+
+impl From<serde_json::Error> for SaveStateError {
+	fn from(err: serde_json::Error) -> Self {
+		SaveStateError::Format(err)
+	}
+}
+
+pub fn save(path: &Path, settings: GameSettings, snapshot: Snapshot) -> Result<(), SaveStateError> {
+	let data = serde_json::to_string(&SaveState {
+		version: SAVESTATE_VERSION,
+		settings,
+		snapshot,
+	})?;
+
+	fs::write(path, data)?;
+
+	Ok(())
+}
+
+pub fn load(path: &Path) -> Result<(GameSettings, Snapshot), SaveStateError> {
+	let data = fs::read_to_string(path)?;
+
+	let SaveStateVersion { version } = serde_json::from_str(&data)?;
+
+	if version != SAVESTATE_VERSION {
+		return Err(SaveStateError::WrongVersion(version));
+	}
+
+	let state: SaveState = serde_json::from_str(&data)?;
+
+	if !state.settings.board.is_playable() {
+		return Err(SaveStateError::UnplayableBoard(state.settings.board));
+	}
+
+	let board = state.snapshot.board();
+
+	if !board.is_well_formed() {
+		return Err(SaveStateError::UnplayableBoard(board.size));
+	}
+
+	Ok((state.settings, state.snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{board::DEFAULT_BOARD_SIZE, GameState};
+
+	#[test]
+	fn unplayable_boards_dont_load() {
+		let path = std::env::temp_dir().join("rgm-test-unplayable.savestate");
+		let settings = GameSettings {
+			board: BoardSize {
+				hidden: 0,
+				..DEFAULT_BOARD_SIZE
+			},
+			..Default::default()
+		};
+		let game = GameState::new(settings.clone(), 1);
+
+		save(&path, settings, Snapshot::take(&game)).unwrap();
+
+		assert!(matches!(
+			load(&path),
+			Err(SaveStateError::UnplayableBoard(_))
+		));
+	}
+
+	#[test]
+	fn playable_boards_load() {
+		let path = std::env::temp_dir().join("rgm-test-playable.savestate");
+		let game = GameState::new(GameSettings::default(), 1);
+
+		save(&path, GameSettings::default(), Snapshot::take(&game)).unwrap();
+
+		assert!(load(&path).is_ok());
+	}
+}
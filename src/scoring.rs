@@ -1,32 +1,47 @@
 use std::time::{Duration, SystemTime};
 
-use strum_macros::Display;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
 
-use crate::GameState;
+use crate::{
+	grading::GradeEvent,
+	movement::{LastMove, Twist},
+	piece::PieceType,
+	ActiveState, GameState,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct GMCondition {
 	pub score: i32,
 	pub time: Duration,
 }
 
 // To get GM, you need to achieve certain things at certain times.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct GMRequirements {
 	pub three_hundred: Option<GMCondition>,
 	pub five_hundred: Option<GMCondition>,
 	pub game_end: Option<GMCondition>,
 }
 
-#[derive(Debug, Clone, Copy)]
+// 000-099 through 900-999
+pub const SECTION_COUNT: usize = 10;
+// Shirase goes on to 1300
+pub const MAX_SECTION_COUNT: usize = 13;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct PlayerRecord {
 	pub score: i32,
 	pub gm_requirements: GMRequirements,
 	pub start_time: SystemTime,
 	pub grade: Grade,
+	// the frame each section was finished on
+	pub sections: [Option<u32>; MAX_SECTION_COUNT],
+	// singles, doubles, triples and tetrises
+	pub lines: [i32; 4],
 }
 
-#[derive(Debug, Display, Clone, Copy)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
 pub enum Grade {
 	N9,
 	N8,
@@ -47,99 +62,389 @@ pub enum Grade {
 	S7,
 	S8,
 	S9,
+
+	// TGM3's master grades
+	#[strum(serialize = "m1")]
+	M1,
+	#[strum(serialize = "m2")]
+	M2,
+	#[strum(serialize = "m3")]
+	M3,
+	#[strum(serialize = "m4")]
+	M4,
+	#[strum(serialize = "m5")]
+	M5,
+	#[strum(serialize = "m6")]
+	M6,
+	#[strum(serialize = "m7")]
+	M7,
+	#[strum(serialize = "m8")]
+	M8,
+	#[strum(serialize = "m9")]
+	M9,
+
+	// TAP's master grade
+	M,
 	GM,
+
+	// Shirase's grades go past S9
+	S10,
+	S11,
+	S12,
+	S13,
 }
 
-fn is_gm(record: &PlayerRecord) -> bool {
-	let one_second = Duration::new(1, 0);
-	let one_minute = one_second * 60;
-	let four_min_15_sec = one_minute * 4 + one_second * 15;
-	let seven_min_30_sec = one_minute * 7 + one_second * 30;
-	let thirteen_min_30_sec = one_minute * 13 + one_second * 30;
-
-	if let (Some(three_hundred), Some(five_hundred), Some(game_end)) = (
-		record.gm_requirements.three_hundred,
-		record.gm_requirements.five_hundred,
-		record.gm_requirements.game_end,
-	) {
-		if three_hundred.score < 12_000 || three_hundred.time <= four_min_15_sec {
-			return false;
-		}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, Display)]
+pub enum ScoringKind {
+	#[strum(serialize = "TGM1")]
+	Tgm1,
+	// TGM1's, plus bonuses for sonic drops and locking quickly
+	#[strum(serialize = "TAP")]
+	Tap,
+	#[strum(serialize = "TGM3")]
+	Tgm3,
+	// back-to-backs, T-spins, combos and perfect clears
+	Guideline,
+}
 
-		if five_hundred.score < 40_000 || five_hundred.time <= seven_min_30_sec {
-			return false;
+// what a scoring system gets told when a piece locks.
+#[derive(Debug, Clone, Copy)]
+pub struct LockEvent {
+	pub piece: PieceType,
+	// 0 if the piece didn't clear anything
+	pub lines: i32,
+	// frames down was held for while the piece was active
+	pub soft_frames: i32,
+	// rows the piece fell from sonic drops
+	pub sonic_rows: i32,
+	// frames the piece was active for, and how long it could have sat on the floor
+	pub active_frames: i32,
+	pub lock_delay: i32,
+	// times the piece was rotated
+	pub rotations: i32,
+	// the last thing that moved it was a rotation that needed a kick
+	pub kicked: bool,
+	pub twist: Twist,
+	// the clear emptied the board
+	pub bravo: bool,
+	// consecutive line clearing pieces, counting this one
+	pub combo: i32,
+	pub level_before: i32,
+	pub level_after: i32,
+}
+
+pub trait ScoringSystem {
+	// how many points the lock was worth.
+	fn on_lock(&mut self, event: &LockEvent) -> i32;
+}
+
+// the scoring system for each kind, kept as an enum so it can go in snapshots and savestates.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Scorer {
+	Tgm1(Tgm1Scoring),
+	Tap(TapScoring),
+	Tgm3(TapScoring),
+	Guideline(GuidelineScoring),
+}
+
+impl Scorer {
+	pub fn new(kind: ScoringKind) -> Self {
+		match kind {
+			ScoringKind::Tgm1 => Scorer::Tgm1(Tgm1Scoring::default()),
+			ScoringKind::Tap => Scorer::Tap(TapScoring::new(2)),
+			// TGM3 is TAP without the sonic drop bonus doubled
+			ScoringKind::Tgm3 => Scorer::Tgm3(TapScoring::new(1)),
+			ScoringKind::Guideline => Scorer::Guideline(GuidelineScoring::default()),
 		}
+	}
 
-		if game_end.score < 126_000 || game_end.time <= thirteen_min_30_sec {
-			return false;
+	pub fn system_mut(&mut self) -> &mut dyn ScoringSystem {
+		match self {
+			Scorer::Tgm1(s) => s,
+			Scorer::Tap(s) => s,
+			Scorer::Tgm3(s) => s,
+			Scorer::Guideline(s) => s,
 		}
+	}
+}
 
-		true
+// TGM's combo starts at 1 and goes up by 2 for every line past the first,
+// so singles don't build it. a piece that doesn't clear puts it back to 1.
+fn next_combo(combo: i32, lines_cleared: i32) -> i32 {
+	if lines_cleared > 0 {
+		combo + 2 * lines_cleared - 2
 	} else {
-		false
+		1
 	}
 }
 
-fn get_grade(record: &PlayerRecord) -> Grade {
-	let score = record.score;
+// the part every TGM game shares. drops are the soft and sonic drop
+// bonus, and combo already counts this clear.
+fn tgm_line_clear_score(event: &LockEvent, drops: i32, combo: i32) -> i32 {
+	let base = f32::ceil((event.level_before + event.lines) as f32 / 4.0) as i32;
 
-	if is_gm(record) {
-		return Grade::GM;
+	let bravo = if event.bravo { 4 } else { 1 };
+
+	(base + drops) * event.lines * combo * bravo
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tgm1Scoring {
+	combo: i32,
+}
+
+impl Default for Tgm1Scoring {
+	fn default() -> Self {
+		Tgm1Scoring { combo: 1 }
 	}
+}
+
+impl ScoringSystem for Tgm1Scoring {
+	fn on_lock(&mut self, event: &LockEvent) -> i32 {
+		self.combo = next_combo(self.combo, event.lines);
 
-	match score {
-		120_000.. => Grade::S9,
-		100_000.. => Grade::S8,
-		82_000.. => Grade::S7,
-		66_000.. => Grade::S6,
-		52_000.. => Grade::S5,
-		40_000.. => Grade::S4,
-		30_000.. => Grade::S3,
-		22_000.. => Grade::S2,
-		16_000.. => Grade::S1,
-
-		12_000.. => Grade::N1,
-		8_000.. => Grade::N2,
-		5_500.. => Grade::N3,
-		3_500.. => Grade::N4,
-		2_000.. => Grade::N5,
-		1_400.. => Grade::N6,
-		800.. => Grade::N7,
-		400.. => Grade::N8,
-		_ => Grade::N9,
+		tgm_line_clear_score(event, event.soft_frames, self.combo)
 	}
 }
 
-fn get_line_clear_score(
-	level: i32,
-	lines_cleared: i32,
-	frames_down_held: i32,
+// TAP adds half the new level and 7 points for every frame of lock delay
+// the piece didn't use.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TapScoring {
 	combo: i32,
-	is_bravo: bool,
-) -> i32 {
-	let base = f32::ceil((level + lines_cleared) as f32 / 4.0) as i32;
+	// how much each sonic dropped row is worth
+	sonic_multiplier: i32,
+}
 
-	let bravo = if is_bravo { 4 } else { 1 };
+impl TapScoring {
+	fn new(sonic_multiplier: i32) -> Self {
+		TapScoring {
+			combo: 1,
+			sonic_multiplier,
+		}
+	}
+}
+
+impl ScoringSystem for TapScoring {
+	fn on_lock(&mut self, event: &LockEvent) -> i32 {
+		self.combo = next_combo(self.combo, event.lines);
+
+		if event.lines == 0 {
+			return 0;
+		}
+
+		let drops = event.soft_frames + self.sonic_multiplier * event.sonic_rows;
+		let speed = (event.lock_delay - event.active_frames).max(0);
+
+		tgm_line_clear_score(event, drops, self.combo) + (event.level_after + 1) / 2 + speed * 7
+	}
+}
 
-	(base + frames_down_held) * lines_cleared * combo * bravo
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct GuidelineScoring {
+	// the last clear was a tetris or a T-spin
+	back_to_back: bool,
 }
 
-pub fn update_record(state: &mut GameState, lines_cleared: i32, frames_down_held: i32) {
-	let is_bravo = state.board.is_empty();
+impl ScoringSystem for GuidelineScoring {
+	fn on_lock(&mut self, event: &LockEvent) -> i32 {
+		// guideline levels go 1 to 15ish, so every section is a level
+		let level = event.level_before / 100 + 1;
+		let lines = event.lines.clamp(0, 4) as usize;
 
-	state.player_record.score += get_line_clear_score(
-		state.level,
-		lines_cleared,
-		frames_down_held,
-		state.current_combo,
-		is_bravo,
-	);
+		// only T-spins score. a mini that needed a kick to get in counts as
+		// a full one, like the guideline's upgrade for the T-spin triple kick.
+		let twist = match (event.piece, event.twist, event.kicked) {
+			(PieceType::T, Twist::Mini, true) => Twist::Full,
+			(PieceType::T, twist, _) => twist,
+			_ => Twist::None,
+		};
 
-	state.level += if lines_cleared > 999 {
+		let clear = match twist {
+			Twist::None => [0, 100, 300, 500, 800][lines],
+			Twist::Mini => [100, 200, 400, 400, 400][lines],
+			Twist::Full => [400, 800, 1200, 1600, 1600][lines],
+		};
+
+		let difficult = lines == 4 || (lines > 0 && twist != Twist::None);
+		let back_to_back = difficult && self.back_to_back;
+
+		// a twist that doesn't clear doesn't break a back-to-back
+		if lines > 0 {
+			self.back_to_back = difficult;
+		}
+
+		let clear = if back_to_back { clear * 3 / 2 } else { clear };
+
+		let perfect_clear = match (event.bravo, back_to_back) {
+			(false, _) => 0,
+			(true, true) if lines == 4 => 3200,
+			(true, _) => [0, 800, 1200, 1800, 2000][lines],
+		};
+
+		let combo = if lines > 0 { 50 * (event.combo - 1) } else { 0 };
+
+		// there's no counting cells here, so a point for each frame of soft drop
+		let drops = event.soft_frames + 2 * event.sonic_rows;
+
+		(clear + perfect_clear + combo) * level + drops
+	}
+}
+
+pub fn update_record(state: &mut GameState, lines_cleared: i32, piece: &ActiveState, twist: Twist) {
+	// the lines are already gone, so this is whether the clear emptied the board
+	let bravo = lines_cleared > 0 && state.board.is_empty();
+
+	if (1..=4).contains(&lines_cleared) {
+		state.player_record.lines[lines_cleared as usize - 1] += 1;
+	}
+
+	let level_before = state.level;
+	let lock_delay = state.timings().lock_delay;
+
+	state.add_levels(if lines_cleared > 999 {
 		999
 	} else {
 		lines_cleared
+	});
+
+	let event = LockEvent {
+		piece: piece.piece.p_type,
+		lines: lines_cleared,
+		soft_frames: piece.down_frames,
+		sonic_rows: piece.sonic_rows,
+		active_frames: piece.active_frames,
+		lock_delay,
+		rotations: piece.rotations,
+		kicked: piece.last_move == LastMove::Kick,
+		twist,
+		bravo,
+		combo: state.current_combo,
+		level_before,
+		level_after: state.level,
 	};
 
-	state.player_record.grade = get_grade(&state.player_record);
+	state.player_record.score += state.scorer.system_mut().on_lock(&event);
+	state.medals.on_lock(&event, state.board.filled_count());
+
+	state.grader.system_mut().on_lock(&GradeEvent {
+		lines: lines_cleared,
+		level_before,
+		combo: state.current_combo,
+		score: state.player_record.score,
+		gm_requirements: state.player_record.gm_requirements,
+	});
+
+	state.player_record.grade = state.grader.system().grade();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn lock(level_before: i32, lines: i32, soft_frames: i32, bravo: bool) -> LockEvent {
+		LockEvent {
+			piece: PieceType::T,
+			lines,
+			soft_frames,
+			sonic_rows: 0,
+			active_frames: 0,
+			lock_delay: 30,
+			rotations: 0,
+			kicked: false,
+			twist: Twist::None,
+			bravo,
+			combo: 0,
+			level_before,
+			level_after: level_before + lines,
+		}
+	}
+
+	#[test]
+	fn combo_grows_by_lines() {
+		// single, double, triple
+		assert_eq!(next_combo(1, 1), 1);
+		assert_eq!(next_combo(1, 2), 3);
+		assert_eq!(next_combo(3, 3), 7);
+		// a tetris alone is worth a big combo
+		assert_eq!(next_combo(1, 4), 7);
+		assert_eq!(next_combo(7, 0), 1);
+	}
+
+	// pieces worked by hand from the formula documented on TetrisConcept's TGM1
+	// scoring page: (ceil((level + lines) / 4) + soft) * lines * combo * bravo.
+	// these aren't recorded games, so they only check the code does what the
+	// formula says. each piece is (level before, lines, soft drop frames, bravo,
+	// combo after, points), then the run's total.
+	type WorkedPiece = (i32, i32, i32, bool, i32, i32);
+
+	const TGM1_WORKED_EXAMPLES: [(&[WorkedPiece], i32); 3] = [
+		// combo growth: single, double, triple, then a piece that misses
+		(
+			&[
+				(0, 1, 0, false, 1, 1),
+				(5, 2, 4, false, 3, 36),
+				(10, 3, 0, false, 7, 84),
+				(13, 0, 6, false, 1, 0),
+			],
+			121,
+		),
+		// the level rounds up before soft drop is added
+		(
+			&[
+				(98, 1, 0, false, 1, 25),
+				(99, 4, 10, false, 7, 1008),
+				(103, 0, 0, false, 1, 0),
+				(250, 2, 20, false, 3, 498),
+			],
+			1531,
+		),
+		// a clear that empties the board is worth four times as much
+		(
+			&[
+				(300, 4, 2, true, 7, 8736),
+				(304, 0, 0, false, 1, 0),
+				(997, 2, 0, true, 3, 6000),
+			],
+			14736,
+		),
+	];
+
+	#[test]
+	fn sonic_drops_are_worth_more_in_tap() {
+		let event = LockEvent {
+			sonic_rows: 10,
+			active_frames: 30,
+			..lock(0, 1, 0, false)
+		};
+
+		// (ceil(1 / 4) + sonic rows * multiplier) + ceil(level after / 2), no speed bonus
+		assert_eq!(
+			Scorer::new(ScoringKind::Tap).system_mut().on_lock(&event),
+			22
+		);
+		assert_eq!(
+			Scorer::new(ScoringKind::Tgm3).system_mut().on_lock(&event),
+			12
+		);
+	}
+
+	#[test]
+	fn tgm1_formula_worked_examples() {
+		for (pieces, expected_total) in TGM1_WORKED_EXAMPLES {
+			let mut scoring = Tgm1Scoring::default();
+			let mut total = 0;
+
+			for &(level, lines, soft, bravo, combo, points) in pieces {
+				let scored = scoring.on_lock(&lock(level, lines, soft, bravo));
+
+				assert_eq!(scoring.combo, combo, "combo at level {}", level);
+				assert_eq!(scored, points, "points at level {}", level);
+
+				total += scored;
+			}
+
+			assert_eq!(total, expected_total);
+		}
+	}
 }
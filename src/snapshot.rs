@@ -0,0 +1,122 @@
+// snapshots of the game, taken every time a piece locks.
+// practice mode uses these to undo pieces and rewind time.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	board::Board,
+	grading::Grader,
+	medals::Medals,
+	piece::PieceType,
+	randomiser::Randomiser,
+	scoring::{PlayerRecord, Scorer},
+	GameState, Roll, State, FPS,
+};
+
+// how many locks we remember. a board is a few KB, so this is plenty.
+const SNAPSHOT_LIMIT: usize = 600;
+
+pub const REWIND_SECONDS: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+	frames: u32,
+	state: State,
+	randomiser: Randomiser,
+	next_piece: PieceType,
+	forced_queue: VecDeque<PieceType>,
+	level: i32,
+	current_combo: i32,
+	player_record: PlayerRecord,
+	grader: Grader,
+	scorer: Scorer,
+	medals: Medals,
+	garbage_pieces: i32,
+	roll: Option<Roll>,
+	board: Board,
+}
+
+impl Snapshot {
+	pub fn take(game: &GameState) -> Self {
+		Snapshot {
+			frames: game.frames,
+			state: game.state,
+			randomiser: game.randomiser,
+			next_piece: game.next_piece,
+			forced_queue: game.forced_queue.clone(),
+			level: game.level,
+			current_combo: game.current_combo,
+			scorer: game.scorer,
+			medals: game.medals,
+			garbage_pieces: game.garbage_pieces,
+			roll: game.roll,
+			player_record: game.player_record,
+			grader: game.grader,
+			board: game.board.clone(),
+		}
+	}
+
+	pub fn board(&self) -> &Board {
+		&self.board
+	}
+
+	pub fn restore(&self, game: &mut GameState) {
+		game.frames = self.frames;
+		game.state = self.state;
+		game.randomiser = self.randomiser;
+		game.next_piece = self.next_piece;
+		game.forced_queue = self.forced_queue.clone();
+		game.level = self.level;
+		game.current_combo = self.current_combo;
+		game.scorer = self.scorer;
+		game.medals = self.medals;
+		game.garbage_pieces = self.garbage_pieces;
+		game.roll = self.roll;
+		game.player_record = self.player_record;
+		game.grader = self.grader;
+		game.board = self.board.clone();
+
+		// the replay goes back in time with us
+		if let Some(inputs) = &mut game.inputs {
+			inputs.truncate(self.frames as usize);
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Snapshots {
+	buf: VecDeque<Snapshot>,
+}
+
+impl Snapshots {
+	pub fn push(&mut self, snapshot: Snapshot) {
+		if self.buf.len() >= SNAPSHOT_LIMIT {
+			self.buf.pop_front();
+		}
+
+		self.buf.push_back(snapshot);
+	}
+
+	// the newest snapshot is the lock that led to the current piece,
+	// so undoing that piece means going back one further.
+	pub fn undo(&mut self) -> Option<&Snapshot> {
+		if self.buf.len() > 1 {
+			self.buf.pop_back();
+		}
+
+		self.buf.back()
+	}
+
+	// goes back to the newest snapshot at least `seconds` before `frames`.
+	pub fn rewind(&mut self, frames: u32, seconds: u32) -> Option<&Snapshot> {
+		let target = frames.saturating_sub(seconds * FPS);
+
+		while self.buf.len() > 1 && self.buf.back().is_some_and(|s| s.frames > target) {
+			self.buf.pop_back();
+		}
+
+		self.buf.back()
+	}
+}